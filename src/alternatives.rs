@@ -0,0 +1,321 @@
+use std::{collections::HashSet, sync::Arc};
+
+use crate::{
+    resolve::{Resolution, SymbolEntry, SymbolTable},
+    tree_sitter_cli::{
+        grammars::{Variable, VariableType},
+        prepare_grammar::ExtractedSyntaxGrammar,
+        rules::{Alias, Rule},
+    },
+};
+
+/// One alternative of the top-level choice of a variable, like `if_statement` for `statement`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Alternative {
+    /// The node the alternative produces. `None` for alternatives without a node of their own,
+    /// like sequences.
+    pub tag: Option<AlternativeTag>,
+    /// `None` if the alternative could not be resolved.
+    pub resolution: Option<Arc<Resolution>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AlternativeTag {
+    /// A visible symbol, either named like `identifier` or anonymous like `true`.
+    Symbol {
+        name: String,
+        is_named: bool,
+    },
+    Alias(Alias),
+}
+
+impl AlternativeTag {
+    /// The kind of the node in the syntax tree.
+    pub fn kind(&self) -> &str {
+        match self {
+            AlternativeTag::Symbol { name, .. } => name,
+            AlternativeTag::Alias(alias) => &alias.value,
+        }
+    }
+
+    /// Whether the node is named, rather than anonymous like the nodes of strings.
+    pub fn is_named(&self) -> bool {
+        match self {
+            AlternativeTag::Symbol { is_named, .. } => *is_named,
+            AlternativeTag::Alias(alias) => alias.is_named,
+        }
+    }
+
+    pub(crate) fn symbol(entry: &SymbolEntry) -> Self {
+        AlternativeTag::Symbol {
+            name: entry.name.clone(),
+            is_named: entry.kind == VariableType::Named,
+        }
+    }
+}
+
+impl Alternative {
+    pub fn text(&self) -> Option<String> {
+        self.resolution
+            .as_ref()
+            .map(|resolution| resolution.derivation.to_string())
+    }
+}
+
+/// Lists the alternatives of the top-level choice of the non-terminal at `index`. Alternatives
+/// that are hidden variables or supertypes don't show up in the syntax tree, and are replaced by
+/// their own alternatives. A rule that isn't a choice is a single alternative, and blank
+/// alternatives are left out.
+///
+/// Symbols come with the examples of `symbol_table`, while `resolve` resolves the other
+/// alternatives as part of the rule of the given variable.
+pub(crate) fn alternatives(
+    grammar: &ExtractedSyntaxGrammar,
+    symbol_table: &SymbolTable,
+    index: usize,
+    resolve: impl Fn(&Variable, &Rule) -> Option<Resolution>,
+) -> Vec<Alternative> {
+    let mut expander = Expander {
+        grammar,
+        symbol_table,
+        resolve,
+        expanded: HashSet::from([index]),
+        alternatives: vec![],
+    };
+    expander.expand(index, &grammar.variables[index].rule);
+    expander.alternatives
+}
+
+struct Expander<'a, F> {
+    grammar: &'a ExtractedSyntaxGrammar,
+    symbol_table: &'a SymbolTable,
+    resolve: F,
+    /// The non-terminals expanded so far, which are not expanded again.
+    expanded: HashSet<usize>,
+    alternatives: Vec<Alternative>,
+}
+
+impl<F: Fn(&Variable, &Rule) -> Option<Resolution>> Expander<'_, F> {
+    /// Adds the alternatives of `rule`, which is part of the rule of the non-terminal at `index`.
+    fn expand(&mut self, index: usize, rule: &Rule) {
+        match rule {
+            Rule::Blank => {}
+            Rule::Choice(rules) => {
+                for rule in rules {
+                    self.expand(index, rule);
+                }
+            }
+            Rule::Metadata { params, rule } => match &params.alias {
+                Some(alias) => {
+                    let resolution = (self.resolve)(&self.grammar.variables[index], rule);
+                    let tag = AlternativeTag::Alias(alias.clone());
+                    self.push(Some(tag), resolution.map(Arc::new));
+                }
+                None => self.expand(index, rule),
+            },
+            Rule::Symbol(symbol) => {
+                let Some(entry) = self.symbol_table.get(*symbol) else {
+                    return;
+                };
+                let is_hidden =
+                    !entry.is_visible() || self.grammar.supertype_symbols.contains(symbol);
+                if symbol.is_non_terminal() && is_hidden {
+                    if self.expanded.insert(symbol.index) {
+                        self.expand(symbol.index, &self.grammar.variables[symbol.index].rule);
+                    }
+                    return;
+                }
+                let tag = entry.is_visible().then(|| AlternativeTag::symbol(entry));
+                self.push(tag, entry.resolution.clone());
+            }
+            _ => {
+                let resolution = (self.resolve)(&self.grammar.variables[index], rule);
+                self.push(None, resolution.map(Arc::new));
+            }
+        }
+    }
+
+    /// Adds an alternative, unless the same kind of node was reached on another path, either as
+    /// a symbol or an alias. A named node and an anonymous one of the same kind are different.
+    fn push(&mut self, tag: Option<AlternativeTag>, resolution: Option<Arc<Resolution>>) {
+        let key = |tag: &AlternativeTag| (tag.kind().to_string(), tag.is_named());
+        let tag_key = tag.as_ref().map(key);
+        if tag_key.is_some()
+            && self
+                .alternatives
+                .iter()
+                .any(|other| other.tag.as_ref().map(key) == tag_key)
+        {
+            return;
+        }
+        self.alternatives.push(Alternative { tag, resolution });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{resolver::Resolver, tree_sitter_cli::parse_grammar::parse_grammar};
+
+    #[test]
+    fn test_alternatives() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "my_lang",
+            "extras": [{ "type": "PATTERN", "value": "\\s" }],
+            "supertypes": ["expression"],
+            "rules": {
+                "statement": {
+                    "type": "CHOICE",
+                    "members": [
+                        { "type": "SYMBOL", "name": "expression_statement" },
+                        { "type": "SYMBOL", "name": "_declaration" },
+                        {
+                            "type": "SEQ",
+                            "members": [
+                                { "type": "STRING", "value": "return" },
+                                { "type": "SYMBOL", "name": "expression" }
+                            ]
+                        },
+                        {
+                            "type": "ALIAS",
+                            "content": { "type": "SYMBOL", "name": "identifier" },
+                            "named": true,
+                            "value": "label"
+                        },
+                        { "type": "BLANK" }
+                    ]
+                },
+                "expression_statement": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "SYMBOL", "name": "expression" },
+                        { "type": "STRING", "value": ";" }
+                    ]
+                },
+                "_declaration": {
+                    "type": "CHOICE",
+                    "members": [
+                        { "type": "SYMBOL", "name": "let_declaration" },
+                        { "type": "SYMBOL", "name": "expression_statement" }
+                    ]
+                },
+                "let_declaration": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "STRING", "value": "let" },
+                        { "type": "SYMBOL", "name": "identifier" }
+                    ]
+                },
+                "expression": {
+                    "type": "CHOICE",
+                    "members": [
+                        { "type": "SYMBOL", "name": "identifier" },
+                        { "type": "SYMBOL", "name": "number" },
+                        { "type": "STRING", "value": "null" },
+                        {
+                            "type": "ALIAS",
+                            "content": { "type": "STRING", "value": "undefined" },
+                            "named": true,
+                            "value": "identifier"
+                        }
+                    ]
+                },
+                "identifier": { "type": "PATTERN", "value": "[a-z]+" },
+                "number": { "type": "PATTERN", "value": "\\d+" }
+            }
+        }"#,
+        )
+        .unwrap();
+        let resolver = Resolver::new(grammar);
+        let resolutions = resolver.resolve();
+        let alternatives = |name: &str| {
+            resolutions
+                .alternatives(name)
+                .unwrap()
+                .into_iter()
+                .map(|alternative| {
+                    let tag = match &alternative.tag {
+                        Some(AlternativeTag::Symbol { name, .. }) => name.clone(),
+                        Some(AlternativeTag::Alias(alias)) => format!("{} (alias)", alias.value),
+                        None => "-".to_string(),
+                    };
+                    (tag, alternative.text().unwrap())
+                })
+                .collect::<Vec<_>>()
+        };
+        let pairs = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(tag, text)| (tag.to_string(), text.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            alternatives("statement"),
+            pairs(&[
+                ("expression_statement", "0;"),
+                ("let_declaration", "let a"),
+                ("-", "return0"),
+                ("label (alias)", "a"),
+            ])
+        );
+        assert_eq!(
+            alternatives("expression"),
+            pairs(&[("identifier", "a"), ("number", "0"), ("null", "null")])
+        );
+        assert_eq!(alternatives("number"), pairs(&[("number", "0")]));
+        assert!(resolutions.alternatives("missing").is_none());
+    }
+
+    #[test]
+    fn test_alternatives_named_and_anonymous() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "my_lang",
+            "rules": {
+                "value": {
+                    "type": "CHOICE",
+                    "members": [
+                        { "type": "STRING", "value": "null" },
+                        {
+                            "type": "ALIAS",
+                            "content": { "type": "STRING", "value": "nil" },
+                            "named": true,
+                            "value": "null"
+                        },
+                        {
+                            "type": "ALIAS",
+                            "content": { "type": "STRING", "value": "none" },
+                            "named": false,
+                            "value": "null"
+                        }
+                    ]
+                }
+            }
+        }"#,
+        )
+        .unwrap();
+        let resolver = Resolver::new(grammar);
+        let resolutions = resolver.resolve();
+        let alternatives: Vec<(String, bool, String)> = resolutions
+            .alternatives("value")
+            .unwrap()
+            .into_iter()
+            .map(|alternative| {
+                let tag = alternative.tag.as_ref().unwrap();
+                let text = alternative.text().unwrap();
+                (tag.kind().to_string(), tag.is_named(), text)
+            })
+            .collect();
+
+        // The named `null` alias is a different kind of node than the `null` string.
+        assert_eq!(
+            alternatives,
+            vec![
+                ("null".to_string(), false, "null".to_string()),
+                ("null".to_string(), true, "nil".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use std::{collections::HashMap, fs, path::Path, str::FromStr, sync::Arc};
+
+use anyhow::{anyhow, Context, Error, Result};
+use serde::Deserialize;
+
+use crate::{
+    cost::CostModel,
+    resolve::{Resolution, SymbolResolutions},
+};
+
+/// File name of the config looked up next to a grammar.
+pub const CONFIG_FILE_NAME: &str = "sapling.toml";
+
+/// Per-language knowledge that can't be derived from the grammar itself.
+///
+/// ```toml
+/// repeat = "minimum"
+///
+/// [repeats]
+/// arguments = 2
+///
+/// [patterns]
+/// '\d+' = "0"
+///
+/// [variables]
+/// identifier = "a"
+///
+/// [externals]
+/// _automatic_semicolon = ";"
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Samples for regex sources, taking precedence over the ones synthesized from the regexes.
+    pub patterns: HashMap<String, String>,
+    /// Examples pinned for variables, used instead of resolving their rules.
+    pub variables: HashMap<String, String>,
+    /// Examples for external tokens, which have no rule to resolve.
+    pub externals: HashMap<String, String>,
+    /// How many copies of their content repetitions are resolved to.
+    pub repeat: RepeatCount,
+    /// Repeat counts for the repetitions in the rules of specific variables, overriding `repeat`.
+    pub repeats: HashMap<String, RepeatCount>,
+}
+
+/// How many copies of its content a repetition is resolved to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RepeatCountValue")]
+pub enum RepeatCount {
+    /// As few as the grammar allows: none for `repeat`, and one for `repeat1`.
+    #[default]
+    Minimum,
+    /// Exactly this many, also for a `repeat` that could be left out, so that examples show how
+    /// list elements are separated. Grammars that tree-sitter would reject get a single copy, as
+    /// there are no tokens to tell whether the copies would merge.
+    Fixed(usize),
+}
+
+/// A repeat count the way it is written in the config, either `"minimum"` or a number.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RepeatCountValue {
+    Number(usize),
+    Name(String),
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config> {
+        let config_str = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&config_str).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Whether the variable's example is pinned rather than resolved.
+    pub fn is_pinned(&self, name: &str) -> bool {
+        self.variables.contains_key(name)
+    }
+
+    /// How many copies the repetitions in the variable's rule are resolved to.
+    pub fn repeat_count(&self, name: &str) -> RepeatCount {
+        self.repeats.get(name).copied().unwrap_or(self.repeat)
+    }
+
+    /// Resolutions for the pinned variables and the external tokens, to start resolving from.
+    pub fn symbol_resolutions(&self, cost_model: CostModel) -> SymbolResolutions {
+        self.externals
+            .iter()
+            .chain(&self.variables)
+            .map(|(name, example)| {
+                (
+                    name.clone(),
+                    Arc::new(Resolution::text(example, cost_model)),
+                )
+            })
+            .collect()
+    }
+}
+
+impl RepeatCount {
+    /// The number of copies of a repetition's content.
+    pub fn copies(&self) -> usize {
+        match *self {
+            RepeatCount::Minimum => 1,
+            RepeatCount::Fixed(count) => count,
+        }
+    }
+}
+
+/// Parses `minimum` or a number of at least 1.
+impl FromStr for RepeatCount {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "minimum" {
+            return Ok(RepeatCount::Minimum);
+        }
+        let count = s
+            .parse()
+            .map_err(|_| anyhow!("Invalid repeat count {s:?}"))?;
+        RepeatCount::try_from(RepeatCountValue::Number(count))
+    }
+}
+
+impl TryFrom<RepeatCountValue> for RepeatCount {
+    type Error = Error;
+
+    fn try_from(value: RepeatCountValue) -> Result<Self, Self::Error> {
+        match value {
+            RepeatCountValue::Number(0) => Err(anyhow!("A repeat count must be at least 1")),
+            RepeatCountValue::Number(count) => Ok(RepeatCount::Fixed(count)),
+            RepeatCountValue::Name(name) => name.parse(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = toml::from_str(
+            r#"
+            repeat = 2
+
+            [patterns]
+            '\d+' = "0"
+            "[^\"]+" = "a"
+
+            [variables]
+            identifier = "x"
+
+            [repeats]
+            statements = "minimum"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                patterns: HashMap::from([
+                    (r"\d+".to_string(), "0".to_string()),
+                    (r#"[^"]+"#.to_string(), "a".to_string()),
+                ]),
+                variables: HashMap::from([("identifier".to_string(), "x".to_string())]),
+                externals: HashMap::new(),
+                repeat: RepeatCount::Fixed(2),
+                repeats: HashMap::from([("statements".to_string(), RepeatCount::Minimum)]),
+            }
+        );
+        assert!(config.is_pinned("identifier"));
+        assert_eq!(config.repeat_count("arguments"), RepeatCount::Fixed(2));
+        assert_eq!(config.repeat_count("statements"), RepeatCount::Minimum);
+
+        assert!(toml::from_str::<Config>("[pattern]").is_err());
+        assert!(toml::from_str::<Config>("repeat = 0").is_err());
+        assert!(toml::from_str::<Config>("repeat = \"many\"").is_err());
+    }
+}
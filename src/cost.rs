@@ -0,0 +1,131 @@
+use std::{ops::Add, str::FromStr};
+
+use anyhow::{anyhow, Error};
+
+/// What an example consists of, for cost models to weigh against each other.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Number of characters in the example text.
+    pub length: usize,
+    /// Number of non-empty tokens in the example.
+    pub tokens: usize,
+    /// Number of nodes in the syntax tree, one for each token and each visible symbol.
+    pub nodes: usize,
+    /// Number of repetitions left out although the repeat count asks for copies of them. Examples
+    /// avoid these before weighing their cost.
+    pub omitted_repeats: usize,
+}
+
+/// Decides which of several examples for a rule is the simplest one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CostModel {
+    #[default]
+    Length,
+    Tokens,
+    Nodes,
+    Weighted {
+        length: usize,
+        tokens: usize,
+        nodes: usize,
+    },
+}
+
+impl Metrics {
+    pub fn token(text: &str) -> Self {
+        let tokens = usize::from(!text.is_empty());
+        Metrics {
+            length: text.chars().count(),
+            tokens,
+            nodes: tokens,
+            omitted_repeats: 0,
+        }
+    }
+}
+
+impl Add for Metrics {
+    type Output = Metrics;
+
+    fn add(self, other: Metrics) -> Metrics {
+        Metrics {
+            length: self.length + other.length,
+            tokens: self.tokens + other.tokens,
+            nodes: self.nodes + other.nodes,
+            omitted_repeats: self.omitted_repeats + other.omitted_repeats,
+        }
+    }
+}
+
+impl CostModel {
+    pub fn cost(&self, metrics: Metrics) -> usize {
+        match *self {
+            CostModel::Length => metrics.length,
+            CostModel::Tokens => metrics.tokens,
+            CostModel::Nodes => metrics.nodes,
+            CostModel::Weighted {
+                length,
+                tokens,
+                nodes,
+            } => length * metrics.length + tokens * metrics.tokens + nodes * metrics.nodes,
+        }
+    }
+}
+
+/// Parses `length`, `tokens`, `nodes` or `weighted:<length>,<tokens>,<nodes>`.
+impl FromStr for CostModel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "length" => Ok(CostModel::Length),
+            "tokens" => Ok(CostModel::Tokens),
+            "nodes" => Ok(CostModel::Nodes),
+            _ => {
+                let weights = s
+                    .strip_prefix("weighted:")
+                    .ok_or_else(|| anyhow!("Unknown cost model {s:?}"))?
+                    .split(',')
+                    .map(|weight| weight.trim().parse())
+                    .collect::<Result<Vec<usize>, _>>()?;
+                match weights[..] {
+                    [length, tokens, nodes] => Ok(CostModel::Weighted {
+                        length,
+                        tokens,
+                        nodes,
+                    }),
+                    _ => Err(anyhow!("Expected three weights in cost model {s:?}")),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_models() {
+        let metrics = Metrics::token("let") + Metrics::token("a") + Metrics::token("");
+        assert_eq!(
+            metrics,
+            Metrics {
+                length: 4,
+                tokens: 2,
+                nodes: 2,
+                omitted_repeats: 0
+            }
+        );
+
+        let table = [
+            ("length", 4),
+            ("tokens", 2),
+            ("nodes", 2),
+            ("weighted:1,10,100", 224),
+        ];
+        for (model, cost) in table {
+            assert_eq!(model.parse::<CostModel>().unwrap().cost(metrics), cost);
+        }
+        assert!("weighted:1,2".parse::<CostModel>().is_err());
+        assert!("shortest".parse::<CostModel>().is_err());
+    }
+}
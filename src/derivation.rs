@@ -0,0 +1,99 @@
+use std::{fmt, sync::Arc};
+
+use crate::{resolve::Resolution, tree_sitter_cli::rules::MetadataParams};
+
+/// An example for a rule, keeping track of which part of the grammar produced which part of the
+/// text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Derivation {
+    /// Text produced by a `Rule::String`, a `Rule::Pattern` sample or a `Rule::Blank`.
+    Text(String),
+    Seq(Vec<Derivation>),
+    /// The resolution of a grammar variable referenced by name.
+    Symbol {
+        name: String,
+        resolution: Arc<Resolution>,
+    },
+    /// A field, alias, precedence or token wrapped around the inner derivation.
+    Metadata {
+        params: MetadataParams,
+        derivation: Box<Derivation>,
+    },
+    /// Text the lexer skips, put between two tokens that would be lexed as one otherwise.
+    Separator(String),
+}
+
+/// A token at either end of an example.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TokenEdge {
+    /// The index of the token in the lexical grammar, `None` for externals and text that did not
+    /// come from a known token.
+    pub index: Option<usize>,
+    pub text: String,
+}
+
+/// The first and last token of an example, so that sequences can tell whether the tokens they
+/// put next to each other would merge. Both are `None` for an empty example.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct TokenEdges {
+    pub first: Option<TokenEdge>,
+    pub last: Option<TokenEdge>,
+}
+
+impl TokenEdges {
+    /// An example consisting of the single token `text`.
+    pub(crate) fn token(index: Option<usize>, text: &str) -> Self {
+        if text.is_empty() {
+            return TokenEdges::default();
+        }
+        let edge = TokenEdge {
+            index,
+            text: text.to_string(),
+        };
+        TokenEdges {
+            first: Some(edge.clone()),
+            last: Some(edge),
+        }
+    }
+
+    pub(crate) fn of(derivation: &Derivation) -> Self {
+        match derivation {
+            Derivation::Text(text) => TokenEdges::token(None, text),
+            Derivation::Seq(derivations) => derivations
+                .iter()
+                .map(TokenEdges::of)
+                .fold(TokenEdges::default(), TokenEdges::then),
+            Derivation::Symbol { resolution, .. } => resolution.edges.clone(),
+            Derivation::Metadata { params, derivation } if params.is_token => {
+                TokenEdges::token(None, &derivation.to_string())
+            }
+            Derivation::Metadata { derivation, .. } => TokenEdges::of(derivation),
+            Derivation::Separator(_) => TokenEdges::default(),
+        }
+    }
+
+    /// The edges of this example followed by another one.
+    pub(crate) fn then(self, next: TokenEdges) -> Self {
+        TokenEdges {
+            first: self.first.or_else(|| next.first.clone()),
+            last: next.last.or(self.last),
+        }
+    }
+}
+
+impl fmt::Display for Derivation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Derivation::Text(text) => f.write_str(text),
+            Derivation::Seq(derivations) => {
+                for derivation in derivations {
+                    derivation.fmt(f)?;
+                }
+                Ok(())
+            }
+            Derivation::Symbol { resolution, .. } => resolution.derivation.fmt(f),
+            Derivation::Metadata { derivation, .. } => derivation.fmt(f),
+            Derivation::Separator(text) => f.write_str(text),
+        }
+    }
+}
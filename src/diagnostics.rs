@@ -0,0 +1,662 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+};
+
+use crate::{
+    resolve::{SymbolResolutions, SymbolTable},
+    tree_sitter_cli::{
+        grammars::{InputGrammar, Variable, VariableType},
+        prepare_grammar::ExtractedLexicalGrammar,
+        rules::{MetadataParams, Rule, Symbol, SymbolType},
+    },
+};
+
+/// A rule that stopped a variable from resolving.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Blocker {
+    MissingPattern(String),
+    /// A reference to another variable that did not resolve either.
+    UnresolvedSymbol(String),
+    /// A reference to an external token without an example in the config.
+    ExternalToken(String),
+    UndefinedSymbol(String),
+    /// A symbol missing from the symbol table, which only has a kind and an index.
+    Symbol(Symbol),
+    /// The word token, when every string it matches is lexed as a keyword instead.
+    Keywords(String),
+}
+
+/// A blocker along with the fields, aliases, precedences and tokens it is wrapped in, outermost
+/// first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockedRule {
+    pub blocker: Blocker,
+    pub metadata: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diagnosis {
+    pub name: String,
+    pub blockers: Vec<BlockedRule>,
+    /// The shortest chain of unresolved variables from this one to one that is either blocked by
+    /// something other than a variable or part of a cycle.
+    pub chain: Vec<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub unresolved: Vec<Diagnosis>,
+    /// Strongly connected components of unresolved variables blocking each other.
+    pub cycles: Vec<Vec<String>>,
+}
+
+/// Whether a symbol that a rule refers to resolved.
+enum SymbolState<'a> {
+    Resolved,
+    Blocked(Blocker),
+    /// A token without an example, blocked by whatever blocks its rule.
+    Token {
+        name: &'a str,
+        rule: &'a Rule,
+    },
+}
+
+/// Where `report` finds out whether the symbols that rules refer to resolved, either by name or
+/// through an interned symbol table.
+trait DiagnosedSymbols {
+    fn named(&self, name: &str) -> SymbolState<'_>;
+    fn interned(&self, symbol: Symbol) -> SymbolState<'_>;
+
+    /// Why a variable has no example even though its rule resolves.
+    fn rejected(&self, _name: &str) -> Option<Blocker> {
+        None
+    }
+}
+
+/// The symbols of a grammar that was resolved by name.
+struct NamedSymbols<'a> {
+    symbol_resolutions: &'a SymbolResolutions,
+    variable_names: HashSet<&'a str>,
+    external_names: HashSet<&'a str>,
+}
+
+/// The symbols of an interned grammar, with the rules of its tokens.
+struct InternedSymbols<'a> {
+    symbol_table: &'a SymbolTable,
+    tokens: &'a ExtractedLexicalGrammar,
+    word_token: Option<usize>,
+}
+
+/// Explains why each variable of the grammar without a resolution could not be resolved.
+pub(crate) fn diagnose(
+    grammar: &InputGrammar,
+    pattern_matches: &HashMap<String, String>,
+    symbol_resolutions: &SymbolResolutions,
+) -> Report {
+    let symbols = NamedSymbols {
+        symbol_resolutions,
+        external_names: grammar
+            .external_tokens
+            .iter()
+            .filter_map(|rule| match rule {
+                Rule::NamedSymbol(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect(),
+        variable_names: grammar
+            .variables
+            .iter()
+            .map(|variable| variable.name.as_str())
+            .collect(),
+    };
+    let unresolved = grammar
+        .variables
+        .iter()
+        .filter(|variable| !symbol_resolutions.contains_key(&variable.name))
+        .map(|variable| (variable.name.as_str(), &variable.rule));
+    report(unresolved, pattern_matches, &symbols)
+}
+
+/// Explains why each variable of the grammar without a resolution could not be resolved, going by
+/// the interned `variables` and `tokens` it was prepared into and resolved from.
+pub(crate) fn diagnose_interned(
+    grammar: &InputGrammar,
+    variables: &[Variable],
+    tokens: &ExtractedLexicalGrammar,
+    word_token: Option<usize>,
+    pattern_matches: &HashMap<String, String>,
+    symbol_table: &SymbolTable,
+) -> Report {
+    let symbols = InternedSymbols {
+        symbol_table,
+        tokens,
+        word_token,
+    };
+    // Variables whose rule is a single token became terminals.
+    let rules = variables
+        .iter()
+        .zip(&symbol_table.non_terminals)
+        .map(|(variable, entry)| (entry, &variable.rule))
+        .chain(
+            tokens
+                .variables
+                .iter()
+                .zip(&symbol_table.terminals)
+                .filter(|(_, entry)| {
+                    matches!(entry.kind, VariableType::Named | VariableType::Hidden)
+                })
+                .map(|(token, entry)| (entry, &token.rule)),
+        )
+        .filter(|(entry, _)| entry.resolution.is_none())
+        .map(|(entry, rule)| (entry.name.as_str(), rule))
+        .collect::<HashMap<_, _>>();
+    let unresolved = grammar
+        .variables
+        .iter()
+        .filter_map(|variable| rules.get_key_value(variable.name.as_str()))
+        .map(|(name, rule)| (*name, *rule));
+    report(unresolved, pattern_matches, &symbols)
+}
+
+/// Explains why each of the `unresolved` variables, given with their rules, could not be resolved.
+fn report<'a>(
+    unresolved: impl Iterator<Item = (&'a str, &'a Rule)>,
+    pattern_matches: &HashMap<String, String>,
+    symbols: &impl DiagnosedSymbols,
+) -> Report {
+    let finder = BlockerFinder {
+        pattern_matches,
+        symbols,
+    };
+    let mut unresolved: Vec<Diagnosis> = unresolved
+        .map(|(name, rule)| {
+            let mut blockers = vec![];
+            if finder.find(rule, &mut vec![], &mut blockers) {
+                blockers.extend(symbols.rejected(name).map(|blocker| BlockedRule {
+                    blocker,
+                    metadata: vec![],
+                }));
+            }
+            Diagnosis {
+                name: name.to_string(),
+                blockers,
+                chain: vec![],
+            }
+        })
+        .collect();
+
+    let blocked_on: HashMap<&str, Vec<&str>> = unresolved
+        .iter()
+        .map(|diagnosis| (diagnosis.name.as_str(), blocking_symbols(diagnosis)))
+        .collect();
+    let cycles = strongly_connected_components(&unresolved, &blocked_on);
+    let cyclic: HashSet<&str> = cycles.iter().flatten().map(String::as_str).collect();
+
+    let chains: Vec<Vec<String>> = unresolved
+        .iter()
+        .map(|diagnosis| blocking_chain(&diagnosis.name, &unresolved, &blocked_on, &cyclic))
+        .collect();
+    for (diagnosis, chain) in unresolved.iter_mut().zip(chains) {
+        diagnosis.chain = chain;
+    }
+
+    Report { unresolved, cycles }
+}
+
+impl DiagnosedSymbols for NamedSymbols<'_> {
+    fn named(&self, name: &str) -> SymbolState<'_> {
+        let blocker = if self.symbol_resolutions.contains_key(name) {
+            return SymbolState::Resolved;
+        } else if self.variable_names.contains(name) {
+            Blocker::UnresolvedSymbol(name.to_string())
+        } else if self.external_names.contains(name) {
+            Blocker::ExternalToken(name.to_string())
+        } else {
+            Blocker::UndefinedSymbol(name.to_string())
+        };
+        SymbolState::Blocked(blocker)
+    }
+
+    fn interned(&self, symbol: Symbol) -> SymbolState<'_> {
+        SymbolState::Blocked(Blocker::Symbol(symbol))
+    }
+}
+
+impl DiagnosedSymbols for InternedSymbols<'_> {
+    fn named(&self, name: &str) -> SymbolState<'_> {
+        SymbolState::Blocked(Blocker::UndefinedSymbol(name.to_string()))
+    }
+
+    fn interned(&self, symbol: Symbol) -> SymbolState<'_> {
+        let Some(entry) = self.symbol_table.get(symbol) else {
+            return SymbolState::Blocked(Blocker::Symbol(symbol));
+        };
+        if entry.resolution.is_some() {
+            return SymbolState::Resolved;
+        }
+        let name = entry.name.clone();
+        let blocker = match symbol.kind {
+            SymbolType::External => Blocker::ExternalToken(name),
+            SymbolType::Terminal
+                if !matches!(entry.kind, VariableType::Named | VariableType::Hidden) =>
+            {
+                return SymbolState::Token {
+                    name: &entry.name,
+                    rule: &self.tokens.variables[symbol.index].rule,
+                };
+            }
+            _ => Blocker::UnresolvedSymbol(name),
+        };
+        SymbolState::Blocked(blocker)
+    }
+
+    fn rejected(&self, name: &str) -> Option<Blocker> {
+        let word_token = &self.symbol_table.terminals[self.word_token?];
+        (word_token.name == name).then(|| Blocker::Keywords(name.to_string()))
+    }
+}
+
+struct BlockerFinder<'a, S> {
+    pattern_matches: &'a HashMap<String, String>,
+    symbols: &'a S,
+}
+
+impl<S: DiagnosedSymbols> BlockerFinder<'_, S> {
+    /// Collects the blockers of a rule, returning whether it resolves at all.
+    fn find(
+        &self,
+        rule: &Rule,
+        metadata: &mut Vec<String>,
+        blockers: &mut Vec<BlockedRule>,
+    ) -> bool {
+        let state = match rule {
+            Rule::NamedSymbol(name) => self.symbols.named(name),
+            Rule::Symbol(symbol) => self.symbols.interned(*symbol),
+            _ => SymbolState::Resolved,
+        };
+        let blocker = match (rule, state) {
+            (_, SymbolState::Blocked(blocker)) => blocker,
+            (_, SymbolState::Token { name, rule }) => {
+                metadata.push(format!("token `{name}`"));
+                let resolves = self.find(rule, metadata, blockers);
+                metadata.pop();
+                return resolves;
+            }
+            (Rule::Blank | Rule::String(_), _) => return true,
+            (Rule::Pattern(s), _) if self.pattern_matches.contains_key(s) => return true,
+            (Rule::Pattern(s), _) => Blocker::MissingPattern(s.clone()),
+            (Rule::NamedSymbol(_) | Rule::Symbol(_), SymbolState::Resolved) => return true,
+            (Rule::Metadata { params, rule }, _) => {
+                metadata.push(describe_metadata(params));
+                let resolves = self.find(rule, metadata, blockers);
+                metadata.pop();
+                return resolves;
+            }
+            (Rule::Repeat(rule), _) => return self.find(rule, metadata, blockers),
+            (Rule::Seq(rules), _) => {
+                let mut resolves = true;
+                for rule in rules {
+                    resolves &= self.find(rule, metadata, blockers);
+                }
+                return resolves;
+            }
+            (Rule::Choice(rules), _) => {
+                // Only report the blockers if no alternative resolves.
+                let mut alternative_blockers = vec![];
+                for rule in rules {
+                    if self.find(rule, metadata, &mut alternative_blockers) {
+                        return true;
+                    }
+                }
+                blockers.extend(alternative_blockers);
+                return false;
+            }
+        };
+        let blocked_rule = BlockedRule {
+            blocker,
+            metadata: metadata.iter().filter(|m| !m.is_empty()).cloned().collect(),
+        };
+        if !blockers.contains(&blocked_rule) {
+            blockers.push(blocked_rule);
+        }
+        false
+    }
+}
+
+fn describe_metadata(params: &MetadataParams) -> String {
+    let mut descriptions = vec![];
+    if let Some(field_name) = &params.field_name {
+        descriptions.push(format!("field `{field_name}`"));
+    }
+    if let Some(alias) = &params.alias {
+        descriptions.push(format!("alias `{}`", alias.value));
+    }
+    if params.is_main_token {
+        descriptions.push("immediate token".to_string());
+    } else if params.is_token {
+        descriptions.push("token".to_string());
+    }
+    if !params.precedence.is_none() {
+        descriptions.push(format!("precedence {}", params.precedence));
+    }
+    descriptions.join(", ")
+}
+
+fn blocking_symbols(diagnosis: &Diagnosis) -> Vec<&str> {
+    let mut names = vec![];
+    for blocked_rule in &diagnosis.blockers {
+        if let Blocker::UnresolvedSymbol(name) = &blocked_rule.blocker {
+            if !names.contains(&name.as_str()) {
+                names.push(name.as_str());
+            }
+        }
+    }
+    names
+}
+
+fn blocking_chain(
+    name: &str,
+    unresolved: &[Diagnosis],
+    blocked_on: &HashMap<&str, Vec<&str>>,
+    cyclic: &HashSet<&str>,
+) -> Vec<String> {
+    let is_root = |name: &str| {
+        cyclic.contains(name)
+            || unresolved.iter().any(|diagnosis| {
+                diagnosis.name == name
+                    && diagnosis
+                        .blockers
+                        .iter()
+                        .any(|b| !matches!(b.blocker, Blocker::UnresolvedSymbol(_)))
+            })
+    };
+
+    let mut previous: HashMap<&str, &str> = HashMap::new();
+    let mut queue = VecDeque::from([name]);
+    let mut root = name;
+    while let Some(current) = queue.pop_front() {
+        if is_root(current) {
+            root = current;
+            break;
+        }
+        for &next in blocked_on.get(current).into_iter().flatten() {
+            if next != name && !previous.contains_key(next) {
+                previous.insert(next, current);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    let mut chain = vec![root.to_string()];
+    let mut current = root;
+    while let Some(&next) = previous.get(current) {
+        chain.push(next.to_string());
+        current = next;
+    }
+    chain.reverse();
+    chain
+}
+
+/// Tarjan's algorithm, keeping only the components that actually form a cycle.
+fn strongly_connected_components(
+    unresolved: &[Diagnosis],
+    blocked_on: &HashMap<&str, Vec<&str>>,
+) -> Vec<Vec<String>> {
+    struct Tarjan<'a> {
+        blocked_on: &'a HashMap<&'a str, Vec<&'a str>>,
+        index: usize,
+        indices: HashMap<&'a str, usize>,
+        low_links: HashMap<&'a str, usize>,
+        stack: Vec<&'a str>,
+        components: Vec<Vec<String>>,
+    }
+
+    impl<'a> Tarjan<'a> {
+        fn visit(&mut self, name: &'a str) {
+            self.indices.insert(name, self.index);
+            self.low_links.insert(name, self.index);
+            self.index += 1;
+            self.stack.push(name);
+
+            for &next in self.blocked_on.get(name).into_iter().flatten() {
+                if !self.indices.contains_key(next) {
+                    self.visit(next);
+                    let low_link = self.low_links[name].min(self.low_links[next]);
+                    self.low_links.insert(name, low_link);
+                } else if self.stack.contains(&next) {
+                    let low_link = self.low_links[name].min(self.indices[next]);
+                    self.low_links.insert(name, low_link);
+                }
+            }
+
+            if self.low_links[name] == self.indices[name] {
+                let position = self.stack.iter().rposition(|&n| n == name).unwrap();
+                let component: Vec<String> =
+                    self.stack.drain(position..).map(String::from).collect();
+                let is_cycle = component.len() > 1
+                    || self
+                        .blocked_on
+                        .get(name)
+                        .is_some_and(|next| next.contains(&name));
+                if is_cycle {
+                    self.components.push(component);
+                }
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        blocked_on,
+        index: 0,
+        indices: HashMap::new(),
+        low_links: HashMap::new(),
+        stack: vec![],
+        components: vec![],
+    };
+    for diagnosis in unresolved {
+        if !tarjan.indices.contains_key(diagnosis.name.as_str()) {
+            tarjan.visit(&diagnosis.name);
+        }
+    }
+    tarjan.components
+}
+
+impl fmt::Display for Blocker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Blocker::MissingPattern(pattern) => write!(f, "missing pattern /{pattern}/"),
+            Blocker::UnresolvedSymbol(name) => write!(f, "unresolved `{name}`"),
+            Blocker::ExternalToken(name) => {
+                write!(
+                    f,
+                    "external token `{name}` without an example in [externals]"
+                )
+            }
+            Blocker::UndefinedSymbol(name) => write!(f, "undefined symbol `{name}`"),
+            Blocker::Symbol(symbol) => {
+                let kind = match symbol.kind {
+                    SymbolType::External => "external token",
+                    SymbolType::End => "end of input",
+                    SymbolType::EndOfNonTerminalExtra => "end of extra",
+                    SymbolType::Terminal => "terminal",
+                    SymbolType::NonTerminal => "non-terminal",
+                };
+                write!(f, "{kind} #{}", symbol.index)
+            }
+            Blocker::Keywords(name) => {
+                write!(f, "word token `{name}` only matches keywords")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnosis in &self.unresolved {
+            writeln!(f, "⛔️ Could not resolve `{}`", diagnosis.name)?;
+            if diagnosis.chain.len() > 1 {
+                writeln!(f, "   blocked on {}", diagnosis.chain.join(" → "))?;
+            }
+            for blocked_rule in &diagnosis.blockers {
+                write!(f, "   {}", blocked_rule.blocker)?;
+                if !blocked_rule.metadata.is_empty() {
+                    write!(f, " (in {})", blocked_rule.metadata.join(", "))?;
+                }
+                writeln!(f)?;
+            }
+        }
+        for cycle in &self.cycles {
+            writeln!(
+                f,
+                "🔁 Unproductive cycle: {} → {}",
+                cycle.join(" → "),
+                cycle[0]
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config, cost::CostModel, resolve::resolve_grammar,
+        tree_sitter_cli::parse_grammar::parse_grammar,
+    };
+
+    #[test]
+    fn test_diagnose() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "my_lang",
+            "externals": [{ "type": "SYMBOL", "name": "heredoc" }],
+            "rules": {
+                "program": {
+                    "type": "CHOICE",
+                    "members": [
+                        { "type": "SYMBOL", "name": "statement" },
+                        { "type": "SYMBOL", "name": "left" }
+                    ]
+                },
+                "statement": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "SYMBOL", "name": "string" },
+                        { "type": "STRING", "value": ";" }
+                    ]
+                },
+                "string": {
+                    "type": "CHOICE",
+                    "members": [
+                        {
+                            "type": "FIELD",
+                            "name": "content",
+                            "content": { "type": "PATTERN", "value": "\"[a-z]\"" }
+                        },
+                        { "type": "SYMBOL", "name": "heredoc" }
+                    ]
+                },
+                "left": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "SYMBOL", "name": "right" },
+                        { "type": "STRING", "value": "<" }
+                    ]
+                },
+                "right": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "STRING", "value": ">" },
+                        { "type": "SYMBOL", "name": "left" }
+                    ]
+                }
+            }
+        }"#,
+        )
+        .unwrap();
+        let pattern_matches = HashMap::new();
+        let symbol_resolutions = resolve_grammar(
+            &grammar,
+            CostModel::Length,
+            &pattern_matches,
+            &Config::default(),
+        );
+
+        let report = diagnose(&grammar, &pattern_matches, &symbol_resolutions);
+        assert_eq!(
+            report.unresolved,
+            vec![
+                Diagnosis {
+                    name: "program".to_string(),
+                    blockers: vec![
+                        BlockedRule {
+                            blocker: Blocker::UnresolvedSymbol("statement".to_string()),
+                            metadata: vec![],
+                        },
+                        BlockedRule {
+                            blocker: Blocker::UnresolvedSymbol("left".to_string()),
+                            metadata: vec![],
+                        },
+                    ],
+                    chain: vec!["program".to_string(), "left".to_string()],
+                },
+                Diagnosis {
+                    name: "statement".to_string(),
+                    blockers: vec![BlockedRule {
+                        blocker: Blocker::UnresolvedSymbol("string".to_string()),
+                        metadata: vec![],
+                    }],
+                    chain: vec!["statement".to_string(), "string".to_string()],
+                },
+                Diagnosis {
+                    name: "string".to_string(),
+                    blockers: vec![
+                        BlockedRule {
+                            blocker: Blocker::MissingPattern("\"[a-z]\"".to_string()),
+                            metadata: vec!["field `content`".to_string()],
+                        },
+                        BlockedRule {
+                            blocker: Blocker::ExternalToken("heredoc".to_string()),
+                            metadata: vec![],
+                        },
+                    ],
+                    chain: vec!["string".to_string()],
+                },
+                Diagnosis {
+                    name: "left".to_string(),
+                    blockers: vec![BlockedRule {
+                        blocker: Blocker::UnresolvedSymbol("right".to_string()),
+                        metadata: vec![],
+                    }],
+                    chain: vec!["left".to_string()],
+                },
+                Diagnosis {
+                    name: "right".to_string(),
+                    blockers: vec![BlockedRule {
+                        blocker: Blocker::UnresolvedSymbol("left".to_string()),
+                        metadata: vec![],
+                    }],
+                    chain: vec!["right".to_string()],
+                },
+            ]
+        );
+        assert_eq!(
+            report.cycles,
+            vec![vec!["left".to_string(), "right".to_string()]]
+        );
+    }
+
+    #[test]
+    fn test_display_symbol_blocker() {
+        assert_eq!(
+            Blocker::Symbol(Symbol::non_terminal(3)).to_string(),
+            "non-terminal #3"
+        );
+        assert_eq!(
+            Blocker::Symbol(Symbol::external(0)).to_string(),
+            "external token #0"
+        );
+    }
+}
@@ -19,17 +19,24 @@ impl<T: Eventuallable> Eventually<T> {
         Default::default()
     }
 
-    pub async fn read(self: &Self) -> T {
-        let inner = self.0.read().await;
+    pub async fn read(&self) -> T {
         loop {
+            let inner = self.0.read().await;
             if let Some(value) = inner.option_value.clone() {
                 return value;
             }
-            inner.notify.notified().await;
+            // Holding on to the lock while waiting would block the write we're waiting for, so
+            // register for the notification first and only then release the lock.
+            let notify = inner.notify.clone();
+            let notified = notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            drop(inner);
+            notified.await;
         }
     }
 
-    pub async fn write(self: &Self, value: T) {
+    pub async fn write(&self, value: T) {
         let mut inner = self.0.write().await;
         inner.option_value = Some(value);
         inner.notify.notify_waiters();
@@ -56,6 +63,18 @@ mod tests {
         });
     }
 
+    #[tokio::test]
+    #[ntest::timeout(50)]
+    async fn read_before_write() {
+        let v = Eventually::new();
+        let v2 = v.clone();
+        let reader = task::spawn(async move { v2.read().await });
+        task::yield_now().await;
+
+        v.write(42).await;
+        assert_eq!(reader.await.unwrap(), 42);
+    }
+
     #[tokio::test]
     #[ntest::timeout(50)]
     #[should_panic]
@@ -0,0 +1,96 @@
+use crate::{
+    token_overlaps::search,
+    tree_sitter_cli::{
+        dfa::Dfa, grammars::LexicalGrammar, prepare_grammar::ExtractedLexicalGrammar, rules::Rule,
+    },
+};
+
+/// Finds the string tokens that the word token matches as a whole, which tree-sitter lexes as
+/// keywords: it first lexes a word, and then checks whether that word is one of them. Returns
+/// the indices of the keywords in the lexical grammar.
+pub(crate) fn extract_keywords(
+    lexical_grammar: &LexicalGrammar,
+    tokens: &ExtractedLexicalGrammar,
+    word_token: usize,
+) -> Vec<usize> {
+    let word = Dfa::new(
+        lexical_grammar,
+        vec![lexical_grammar.variables[word_token].start_state],
+    );
+    tokens
+        .variables
+        .iter()
+        .enumerate()
+        .filter(|(i, token)| {
+            *i != word_token && string_value(&token.rule).is_some_and(|value| word.matches(value))
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// The shortest string the word token matches that is not one of the keywords.
+pub(crate) fn sample_word(
+    lexical_grammar: &LexicalGrammar,
+    word_token: usize,
+    keywords: &[usize],
+) -> Option<String> {
+    let keyword_states = keywords
+        .iter()
+        .map(|i| lexical_grammar.variables[*i].start_state)
+        .collect();
+    let starts = (
+        vec![lexical_grammar.variables[word_token].start_state],
+        keyword_states,
+    );
+    let (sample, _) = search(
+        lexical_grammar,
+        starts,
+        String::new(),
+        true,
+        |word, keywords, _| {
+            word.completions().next().is_some() && keywords.completions().next().is_none()
+        },
+    )?;
+    Some(sample)
+}
+
+/// The string a token matches, if its rule is a single string.
+pub(crate) fn string_value(rule: &Rule) -> Option<&str> {
+    match rule {
+        Rule::String(value) => Some(value),
+        Rule::Metadata { rule, .. } => string_value(rule),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree_sitter_cli::{
+        grammars::Variable, prepare_grammar::expand_tokens, rules::Precedence,
+    };
+
+    #[test]
+    fn test_keywords() {
+        let tokens = ExtractedLexicalGrammar {
+            variables: vec![
+                Variable::anonymous("if", Rule::string("if")),
+                Variable::named("identifier", Rule::pattern("[a-z_]+")),
+                Variable::anonymous("+", Rule::string("+")),
+                Variable::anonymous("a", Rule::prec(Precedence::Integer(1), Rule::string("a"))),
+                Variable::named("number", Rule::pattern(r"\d+")),
+                Variable::anonymous("if_", Rule::string("if_")),
+            ],
+            separators: vec![],
+        };
+        let lexical_grammar = expand_tokens(&tokens).unwrap();
+
+        let keywords = extract_keywords(&lexical_grammar, &tokens, 1);
+        assert_eq!(keywords, vec![0, 3, 5]);
+        assert_eq!(
+            sample_word(&lexical_grammar, 1, &keywords),
+            Some("b".to_string())
+        );
+        assert_eq!(sample_word(&lexical_grammar, 1, &[]), Some("a".to_string()));
+    }
+}
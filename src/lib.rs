@@ -0,0 +1,43 @@
+/*
+Intuition: The more resource constrained worried approach would be to pause every branch once an unresolved symbol is hit,
+build up a dependency graph and then resolve things in order. This could also be run sync.
+
+The async solution has that kind of implicitly where I just run of and try to resolve all the rules where rules halt and await
+when they hit a symbol that can't be resolved. They would then continue whenever that given symbol has been resolved.
+*/
+
+mod alternatives;
+mod config;
+mod cost;
+mod derivation;
+mod diagnostics;
+mod eventually;
+mod keywords;
+mod pattern_samples;
+mod resolve;
+mod resolve_async;
+mod resolver;
+mod sentences;
+mod token_boundaries;
+mod token_overlaps;
+mod tree_sitter_cli;
+
+pub use crate::{
+    alternatives::{Alternative, AlternativeTag},
+    config::{Config, RepeatCount, CONFIG_FILE_NAME},
+    cost::{CostModel, Metrics},
+    derivation::Derivation,
+    diagnostics::{BlockedRule, Blocker, Diagnosis, Report},
+    resolve::{resolve_symbol_table, Resolution, SymbolEntry, SymbolResolutions, SymbolTable},
+    resolver::{Resolutions, Resolver, Status, VariableExample},
+    sentences::{SentenceBound, Sentences},
+    token_overlaps::{OverlapReason, TokenOverlap},
+    tree_sitter_cli::{
+        grammars::{
+            ExternalToken, InputGrammar, PrecedenceEntry, Production, ProductionStep,
+            SyntaxGrammar, SyntaxVariable, Variable, VariableType,
+        },
+        parse_grammar::parse_grammar,
+        rules::{Alias, Associativity, MetadataParams, Precedence, Rule, Symbol, SymbolType},
+    },
+};
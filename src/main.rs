@@ -1,108 +1,197 @@
-/*
-Intuition: The more resource constrained worried approach would be to pause every branch once an unresolved symbol is hit,
-build up a dependency graph and then resolve things in order. This could also be run sync.
-
-The async solution has that kind of implicitly where I just run of and try to resolve all the rules where rules halt and await
-when they hit a symbol that can't be resolved. They would then continue whenever that given symbol has been resolved.
-*/
-
-use std::{collections::HashMap, fs};
-
-use crate::tree_sitter_cli::{parse_grammar::parse_grammar, rules::Rule};
-
-mod tree_sitter_cli;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
+use sapling_sitter::{parse_grammar, Config, CostModel, RepeatCount, Resolver, CONFIG_FILE_NAME};
+
+use crate::output::{render_examples, render_overlaps, render_report, render_validation, Format};
+
+mod output;
+
+/// Synthesizes minimal example sources for the rules of a tree-sitter grammar.
+///
+/// Exits with 0 when every visible rule resolved, 1 when some did not and 2 on errors. `overlaps`
+/// exits with 1 when it found overlapping tokens instead.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
 
-type SymbolResolutions = HashMap<String, String>;
+#[derive(Subcommand)]
+enum Command {
+    /// Prints an example for every rule of the grammar
+    Resolve {
+        #[command(flatten)]
+        grammar: GrammarArgs,
+        #[arg(long, value_enum, default_value_t = Format::Text)]
+        format: Format,
+    },
+    /// Explains why rules could not be resolved
+    Analyze {
+        #[command(flatten)]
+        grammar: GrammarArgs,
+        #[arg(long, value_enum, default_value_t = Format::Text)]
+        format: Format,
+    },
+    /// Checks that every visible rule resolves
+    Validate {
+        #[command(flatten)]
+        grammar: GrammarArgs,
+        #[arg(long, value_enum, default_value_t = Format::Text)]
+        format: Format,
+    },
+    /// Lists the tokens that can match the same string, and which one the lexer picks
+    Overlaps {
+        #[command(flatten)]
+        grammar: GrammarArgs,
+        #[arg(long, value_enum, default_value_t = Format::Text)]
+        format: Format,
+    },
+    /// Writes the examples of every rule to a file
+    Export {
+        #[command(flatten)]
+        grammar: GrammarArgs,
+        #[arg(long, value_enum, default_value_t = Format::Json)]
+        format: Format,
+        /// File to write to, instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
 
-fn quote(str: &str) -> String {
-    format!("\"{str}\"")
+#[derive(Args)]
+struct GrammarArgs {
+    /// A grammar.json, or a grammar directory containing src/grammar.json
+    path: PathBuf,
+    /// How to weigh alternatives: length, tokens, nodes or weighted:<length>,<tokens>,<nodes>
+    #[arg(long, default_value = "length")]
+    cost: CostModel,
+    /// Resolve every group of rules that refer to each other in its own future instead of using
+    /// a single worklist
+    #[arg(long = "async")]
+    use_async: bool,
+    /// Config with samples and pinned examples, instead of the sapling.toml next to the grammar,
+    /// such as one of the configs/<lang>.toml that come with sapling
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// How many copies of their content repetitions get: minimum or a number, overriding the
+    /// config's default. Grammars that tree-sitter would reject get a single copy
+    #[arg(long)]
+    repeat: Option<RepeatCount>,
 }
 
-fn resolve_rule(
-    pattern_matches: &HashMap<String, String>,
-    symbol_resolutions: &mut SymbolResolutions,
-    rule: &Rule,
-) -> Option<String> {
-    match rule {
-        Rule::Blank => Some(String::new()),
-        Rule::String(s) => Some(s.clone()),
-        Rule::Pattern(s) => {
-            if let Some(str) = pattern_matches.get(&s.clone()) {
-                return Some(str.clone());
-            }
-            println!("⛔️️ Missing pattern {}", quote(&s));
-            None
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(error) => {
+            eprintln!("Error: {error:#}");
+            ExitCode::from(2)
         }
-        Rule::Metadata {
-            params: _params,
-            rule: _rule,
-        } => None,
+    }
+}
 
-        Rule::Repeat(rule) => resolve_rule(pattern_matches, symbol_resolutions, rule),
-        Rule::Seq(rules) => {
-            let mut strings = vec![];
-            for rule in rules {
-                if let Some(resolution) = resolve_rule(pattern_matches, symbol_resolutions, rule) {
-                    strings.push(resolution);
-                } else {
-                    return None;
-                }
-            }
+/// Runs the command, returning whether every visible rule resolved, or for `overlaps` whether no
+/// tokens overlap.
+fn run(cli: Cli) -> Result<bool> {
+    let (args, format, output_path) = match &cli.command {
+        Command::Resolve { grammar, format }
+        | Command::Analyze { grammar, format }
+        | Command::Validate { grammar, format }
+        | Command::Overlaps { grammar, format } => (grammar, *format, None),
+        Command::Export {
+            grammar,
+            format,
+            output,
+        } => (grammar, *format, output.as_ref()),
+    };
+    let resolver = resolver(args)?;
+    if let Command::Overlaps { .. } = cli.command {
+        let overlaps =
+            resolver
+                .token_overlaps()
+                .with_context(|| match resolver.preparation_error() {
+                    Some(error) => {
+                        format!("Failed to compile the tokens of the grammar: {error:#}")
+                    }
+                    None => "Failed to compile the tokens of the grammar".to_string(),
+                })?;
+        print!("{}", render_overlaps(format, &overlaps));
+        return Ok(overlaps.is_empty());
+    }
+    let resolutions = if args.use_async {
+        tokio::runtime::Builder::new_current_thread()
+            .build()?
+            .block_on(resolver.resolve_async())
+    } else {
+        resolver.resolve()
+    };
+    for error in resolutions.pattern_errors() {
+        eprintln!("⛔️ {error:#}");
+    }
+    for warning in resolutions.warnings() {
+        eprintln!("⚠️ {warning:#}");
+    }
+    let unresolved = resolutions.unresolved_visible();
+
+    let output = match cli.command {
+        Command::Resolve { .. } | Command::Export { .. } => render_examples(format, &resolutions),
+        Command::Analyze { .. } => render_report(format, &resolutions.diagnose()),
+        Command::Validate { .. } => render_validation(format, &unresolved),
+        Command::Overlaps { .. } => unreachable!(),
+    };
+    match output_path {
+        Some(path) => fs::write(path, output)
+            .with_context(|| format!("Failed to write {}", path.display()))?,
+        None => print!("{output}"),
+    }
 
-            Some(strings.join(""))
-        }
-        Rule::Choice(rules) => rules
-            .into_iter()
-            .map(|rule| resolve_rule(pattern_matches, symbol_resolutions, rule))
-            .filter_map(|r| r)
-            .min(),
+    Ok(unresolved.is_empty())
+}
 
-        Rule::NamedSymbol(name) => symbol_resolutions.get(name).map(|s| s.clone()),
-        Rule::Symbol(sym) => {
-            // TODO
-            dbg!(sym);
-            None
-        }
+/// Finds the grammar.json of a grammar directory, or takes the path as is.
+fn grammar_path(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        path.join("src").join("grammar.json")
+    } else {
+        path.to_path_buf()
     }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let grammar_str = fs::read_to_string("../tree-sitter-typescript/typescript/src/grammar.json")?;
-    let grammar = parse_grammar(&grammar_str)?;
-
-    let pattern_matches: HashMap<String, String> = HashMap::from([
-        // JSON
-        (r#"[^\\"\n]+"#.into(), "".into()),
-        (r#"(\"|\\|\/|b|f|n|r|t|u)"#.into(), "\"".into()),
-        (r#"[^*]*\*+([^/*][^*]*\*+)*"#.into(), "*".into()),
-        (".*".into(), "".into()),
-        ("[0-7]+".into(), "0".into()),
-        (r#"\d+"#.into(), "0".into()),
-        (r#"[\da-fA-F]+"#.into(), "0".into()),
-        ("[1-9]".into(), "1".into()),
-        ("[0-1]+".into(), "0".into()),
-        // TypeScript
-        (
-            r#"[^\x00-\x1F\s\p{Zs}0-9:;`"'@#.,|^&<=>+\-*/\\%?!~()\[\]{}\uFEFF\u2060\u200B]|\\u[0-9a-fA-F]{4}|\\u\{[0-9a-fA-F]+\}"#.into(),
-            "T".into()
-        ),
-        (r#"[a-zA-Z_$][a-zA-Z\d_$]*-[a-zA-Z\d_$\-]*"#.into(), "A-".into()),
-        (".{1,}".into(), "T".into()),
-        ("[^{}<>]+".into(), "T".into()),
-        ("#!.*".into(), "#!".into())
-    ]);
-
-    let mut symbol_resolutions: SymbolResolutions = Default::default();
+/// Finds the config next to the grammar.json, or in the grammar directory.
+fn config_path(path: &Path) -> Option<PathBuf> {
+    let grammar_path = grammar_path(path);
+    [path, grammar_path.parent()?]
+        .iter()
+        .map(|dir| dir.join(CONFIG_FILE_NAME))
+        .find(|path| path.is_file())
+}
 
-    for _n in 0..grammar.variables.len() {
-        for var in &grammar.variables {
-            if let Some(s) = resolve_rule(&pattern_matches, &mut symbol_resolutions, &var.rule) {
-                symbol_resolutions.insert(var.name.clone(), s);
-            }
-        }
+fn resolver(args: &GrammarArgs) -> Result<Resolver> {
+    let path = grammar_path(&args.path);
+    let grammar_str =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let grammar = parse_grammar(&grammar_str)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    let mut config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => match config_path(&args.path) {
+            Some(path) => Config::load(&path)?,
+            None => Config::default(),
+        },
+    };
+    if let Some(repeat) = args.repeat {
+        config.repeat = repeat;
     }
 
-    dbg!(symbol_resolutions);
-
-    Ok(())
+    Ok(Resolver::new(grammar)
+        .with_config(config)
+        .with_cost_model(args.cost))
 }
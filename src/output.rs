@@ -0,0 +1,244 @@
+use std::fmt::Write;
+
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+use sapling_sitter::{Report, Resolutions, TokenOverlap};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Format {
+    Text,
+    Json,
+    Markdown,
+}
+
+/// Lists the example of every variable in grammar order.
+pub(crate) fn render_examples(format: Format, resolutions: &Resolutions) -> String {
+    let examples = resolutions
+        .examples()
+        .map(|example| (example.name, example.text()));
+
+    let mut out = String::new();
+    match format {
+        Format::Text => {
+            for (name, example) in examples {
+                match example {
+                    Some(example) => writeln!(out, "{name}: {example:?}").unwrap(),
+                    None => writeln!(out, "{name}: ⛔️ unresolved").unwrap(),
+                }
+            }
+        }
+        Format::Json => {
+            out = to_json(&serde_json::to_value(resolutions).unwrap());
+        }
+        Format::Markdown => {
+            writeln!(out, "# {}\n", resolutions.grammar().name).unwrap();
+            writeln!(out, "| Rule | Example |\n| --- | --- |").unwrap();
+            for (name, example) in examples {
+                let example = match example {
+                    Some(example) => code_span(&example),
+                    None => "⛔️ unresolved".to_string(),
+                };
+                writeln!(out, "| {} | {example} |", code_span(name)).unwrap();
+            }
+        }
+    }
+    out
+}
+
+/// Renders the diagnostics of the unresolved variables.
+pub(crate) fn render_report(format: Format, report: &Report) -> String {
+    let mut out = String::new();
+    match format {
+        Format::Text => write!(out, "{report}").unwrap(),
+        Format::Json => {
+            let unresolved: Vec<Value> = report
+                .unresolved
+                .iter()
+                .map(|diagnosis| {
+                    let blockers: Vec<Value> = diagnosis
+                        .blockers
+                        .iter()
+                        .map(|blocked_rule| {
+                            json!({
+                                "blocker": blocked_rule.blocker.to_string(),
+                                "metadata": blocked_rule.metadata,
+                            })
+                        })
+                        .collect();
+                    json!({
+                        "name": diagnosis.name,
+                        "chain": diagnosis.chain,
+                        "blockers": blockers,
+                    })
+                })
+                .collect();
+            out = to_json(&json!({
+                "unresolved": unresolved,
+                "cycles": report.cycles,
+            }));
+        }
+        Format::Markdown => {
+            if !report.unresolved.is_empty() {
+                writeln!(out, "## Unresolved rules\n").unwrap();
+            }
+            for diagnosis in &report.unresolved {
+                writeln!(out, "- {}", code_span(&diagnosis.name)).unwrap();
+                if diagnosis.chain.len() > 1 {
+                    let chain: Vec<String> =
+                        diagnosis.chain.iter().map(|name| code_span(name)).collect();
+                    writeln!(out, "  - blocked on {}", chain.join(" → ")).unwrap();
+                }
+                for blocked_rule in &diagnosis.blockers {
+                    write!(out, "  - {}", blocked_rule.blocker).unwrap();
+                    if !blocked_rule.metadata.is_empty() {
+                        write!(out, " (in {})", blocked_rule.metadata.join(", ")).unwrap();
+                    }
+                    writeln!(out).unwrap();
+                }
+            }
+            if !report.cycles.is_empty() {
+                writeln!(out, "\n## Unproductive cycles\n").unwrap();
+            }
+            for cycle in &report.cycles {
+                let cycle: Vec<String> = cycle
+                    .iter()
+                    .chain(&cycle[..1])
+                    .map(|name| code_span(name))
+                    .collect();
+                writeln!(out, "- {}", cycle.join(" → ")).unwrap();
+            }
+        }
+    }
+    out
+}
+
+/// Summarizes whether every visible variable resolved.
+pub(crate) fn render_validation(format: Format, unresolved: &[&str]) -> String {
+    let mut out = String::new();
+    match format {
+        Format::Text => {
+            if unresolved.is_empty() {
+                writeln!(out, "✅ Every visible rule resolved").unwrap();
+            }
+            for name in unresolved {
+                writeln!(out, "⛔️ {name} did not resolve").unwrap();
+            }
+        }
+        Format::Json => {
+            out = to_json(&json!({
+                "valid": unresolved.is_empty(),
+                "unresolved": unresolved,
+            }));
+        }
+        Format::Markdown => {
+            if unresolved.is_empty() {
+                writeln!(out, "✅ Every visible rule resolved").unwrap();
+            }
+            for name in unresolved {
+                writeln!(out, "- ⛔️ {} did not resolve", code_span(name)).unwrap();
+            }
+        }
+    }
+    out
+}
+
+/// Lists the pairs of tokens matching the same string, with the token the lexer picks first.
+pub(crate) fn render_overlaps(format: Format, overlaps: &[TokenOverlap]) -> String {
+    let mut out = String::new();
+    match format {
+        Format::Text => {
+            for overlap in overlaps {
+                write!(
+                    out,
+                    "{} beats {} on {:?} by {}",
+                    overlap.winner, overlap.loser, overlap.witness, overlap.reason
+                )
+                .unwrap();
+                if let Some(longer) = &overlap.loser_wins_on {
+                    write!(out, ", but loses on {longer:?} by length").unwrap();
+                }
+                writeln!(out).unwrap();
+            }
+        }
+        Format::Json => {
+            out = to_json(&json!({ "overlaps": overlaps }));
+        }
+        Format::Markdown => {
+            writeln!(
+                out,
+                "| Winner | Loser | Witness | Reason | Loser wins on |\n| --- | --- | --- | --- | --- |"
+            )
+            .unwrap();
+            for overlap in overlaps {
+                let longer = match &overlap.loser_wins_on {
+                    Some(longer) => code_span(longer),
+                    None => "-".to_string(),
+                };
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {} | {longer} |",
+                    code_span(&overlap.winner),
+                    code_span(&overlap.loser),
+                    code_span(&overlap.witness),
+                    overlap.reason
+                )
+                .unwrap();
+            }
+        }
+    }
+    out
+}
+
+fn to_json(value: &Value) -> String {
+    let mut json = serde_json::to_string_pretty(value).unwrap();
+    json.push('\n');
+    json
+}
+
+/// Wraps text in a Markdown code span that survives backticks, pipes and line breaks in it.
+fn code_span(text: &str) -> String {
+    if text.is_empty() {
+        return "*empty*".to_string();
+    }
+    let text: String = text
+        .chars()
+        .flat_map(|c| match c {
+            '|' => "\\|".chars().collect::<Vec<_>>(),
+            c if c.is_control() => c.escape_default().collect(),
+            c => vec![c],
+        })
+        .collect();
+    let mut longest_run = 0;
+    let mut run = 0;
+    for c in text.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest_run = longest_run.max(run);
+    }
+    let fence = "`".repeat(longest_run + 1);
+    if text.starts_with('`') || text.ends_with('`') {
+        format!("{fence} {text} {fence}")
+    } else {
+        format!("{fence}{text}{fence}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_span() {
+        let table = [
+            ("let", "`let`"),
+            ("", "*empty*"),
+            ("a|b", "`a\\|b`"),
+            ("``", "``` `` ```"),
+            ("a`b", "``a`b``"),
+            ("\n", "`\\n`"),
+        ];
+        for (text, expected) in table {
+            assert_eq!(code_span(text), expected, "{text:?}");
+        }
+    }
+}
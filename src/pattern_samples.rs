@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Error};
+
+use crate::tree_sitter_cli::{
+    grammars::InputGrammar, nfa::NfaCursor, prepare_grammar::expand_pattern, rules::Rule,
+};
+
+/// Maps the regex source of every `Rule::Pattern` in the grammar to an example string it accepts.
+/// Entries in `overrides` win over synthesized ones, so hand-picked samples can still be used,
+/// unless the pattern rejects them. Patterns that fail to compile or accept no string at all are
+/// left out and reported in `errors`, along with the rejected overrides.
+pub(crate) fn pattern_samples(
+    grammar: &InputGrammar,
+    overrides: HashMap<String, String>,
+    errors: &mut Vec<Error>,
+) -> HashMap<String, String> {
+    let mut patterns = vec![];
+    let rules = grammar
+        .variables
+        .iter()
+        .map(|v| &v.rule)
+        .chain(&grammar.extra_symbols)
+        .chain(&grammar.external_tokens);
+    for rule in rules {
+        collect_patterns(rule, &mut patterns);
+    }
+
+    let mut samples = overrides;
+    for pattern in patterns {
+        if let Some(sample) = samples.get(pattern) {
+            // Patterns that don't compile can only be sampled by hand, without a check.
+            if accepts_sample(pattern, sample).unwrap_or(true) {
+                continue;
+            }
+            errors.push(anyhow!("Sample {sample:?} does not match /{pattern}/"));
+            samples.remove(pattern);
+        }
+        match sample_pattern(pattern) {
+            Ok(Some(sample)) => {
+                samples.insert(pattern.clone(), sample);
+            }
+            Ok(None) => errors.push(anyhow!("Pattern /{pattern}/ does not accept any string")),
+            Err(error) => errors.push(error),
+        }
+    }
+    samples
+}
+
+fn sample_pattern(pattern: &str) -> anyhow::Result<Option<String>> {
+    let (nfa, start_state) = expand_pattern(pattern)?;
+    Ok(NfaCursor::new(&nfa, vec![start_state]).shortest_match())
+}
+
+fn accepts_sample(pattern: &str, sample: &str) -> anyhow::Result<bool> {
+    let (nfa, start_state) = expand_pattern(pattern)?;
+    Ok(NfaCursor::new(&nfa, vec![start_state]).matches(sample))
+}
+
+fn collect_patterns<'a>(rule: &'a Rule, patterns: &mut Vec<&'a String>) {
+    match rule {
+        Rule::Pattern(pattern) => {
+            if !patterns.contains(&pattern) {
+                patterns.push(pattern);
+            }
+        }
+        Rule::Metadata { rule, .. } | Rule::Repeat(rule) => collect_patterns(rule, patterns),
+        Rule::Choice(rules) | Rule::Seq(rules) => {
+            for rule in rules {
+                collect_patterns(rule, patterns);
+            }
+        }
+        Rule::Blank | Rule::String(_) | Rule::NamedSymbol(_) | Rule::Symbol(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree_sitter_cli::parse_grammar::parse_grammar;
+
+    #[test]
+    fn test_shortest_match() {
+        let table = [
+            (r"\d+", "0"),
+            (r"[a-z]+(\.[a-z]+)*", "a"),
+            (r"ab?c*", "a"),
+            (r"0[xX][\da-fA-F]+", "0X0"),
+            (r"(abc|de)f", "def"),
+            (r"a{3}", "aaa"),
+            (r".*", ""),
+            // readable characters win over whatever comes first in a set
+            (r"[^\n]+", "0"),
+            (r"[\x00-\x1F!-/]", "!"),
+            (r"\s", " "),
+            (r"[^\x00-\x7F]", "ª"),
+            (r"[\x00-\x08]|ab", "\0"),
+            // of the equally short strings, the one starting with the most readable characters wins
+            (r"[\x00-\x1F]x|-b", "-b"),
+            (r"[\x00-\x1F]x|a[\x00-\x1F]|-b", "a\t"),
+        ];
+        for (pattern, expected) in table {
+            assert_eq!(
+                sample_pattern(pattern).unwrap().as_deref(),
+                Some(expected),
+                "shortest match for /{}/",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn test_pattern_samples_prefer_overrides() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "my_lang",
+            "rules": {
+                "number": { "type": "PATTERN", "value": "\\d+" },
+                "word": { "type": "PATTERN", "value": "[a-z]+" }
+            }
+        }"#,
+        )
+        .unwrap();
+
+        let samples = pattern_samples(
+            &grammar,
+            HashMap::from([("[a-z]+".to_string(), "foo".to_string())]),
+            &mut vec![],
+        );
+        assert_eq!(samples.get(r"\d+").map(String::as_str), Some("0"));
+        assert_eq!(samples.get("[a-z]+").map(String::as_str), Some("foo"));
+
+        let mut errors = vec![];
+        let samples = pattern_samples(
+            &grammar,
+            HashMap::from([(r"\d+".to_string(), "".to_string())]),
+            &mut errors,
+        );
+        assert_eq!(samples.get(r"\d+").map(String::as_str), Some("0"));
+        let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(errors, vec![r#"Sample "" does not match /\d+/"#]);
+    }
+}
@@ -0,0 +1,1039 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use crate::{
+    config::{Config, RepeatCount},
+    cost::{CostModel, Metrics},
+    derivation::{Derivation, TokenEdge, TokenEdges},
+    token_boundaries::TokenBoundaries,
+    tree_sitter_cli::{
+        grammars::{InputGrammar, Variable, VariableType},
+        rules::{MetadataParams, Rule, Symbol, SymbolType},
+    },
+};
+
+/// An example for a rule along with what it costs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resolution {
+    pub derivation: Derivation,
+    pub metrics: Metrics,
+    pub cost: usize,
+    pub(crate) edges: TokenEdges,
+}
+
+pub type SymbolResolutions = HashMap<String, Arc<Resolution>>;
+
+/// Resolutions for the symbols of an interned grammar, with a table for each kind of symbol a
+/// `Rule::Symbol` can point into.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    pub non_terminals: Vec<SymbolEntry>,
+    pub terminals: Vec<SymbolEntry>,
+    pub externals: Vec<SymbolEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SymbolEntry {
+    pub name: String,
+    pub kind: VariableType,
+    pub resolution: Option<Arc<Resolution>>,
+}
+
+/// Where `resolve_rule` finds the resolutions of the symbols a rule refers to, either by name or
+/// through an interned symbol table.
+pub(crate) trait SymbolLookup {
+    fn named(&self, name: &str) -> Option<&Arc<Resolution>>;
+    fn interned(&self, symbol: Symbol) -> Option<&SymbolEntry>;
+
+    /// What to put between two adjacent tokens that the lexer would read as one otherwise.
+    fn separator(&self, _left: &TokenEdge, _right: &TokenEdge) -> Option<&str> {
+        None
+    }
+
+    /// How many copies of their content repetitions are resolved to.
+    fn repeat_count(&self) -> RepeatCount {
+        RepeatCount::Minimum
+    }
+
+    /// Whether `separator` knows which tokens would merge. Repetitions are resolved to a single
+    /// copy otherwise, as the copies could be lexed as one token.
+    fn separates_tokens(&self) -> bool {
+        false
+    }
+}
+
+/// Looks up symbols while resolving the rule of one variable, with the repeat count for its
+/// repetitions.
+struct VariableLookup<'a, S> {
+    symbols: &'a S,
+    repeat_count: RepeatCount,
+}
+
+/// Looks up symbols in `symbols`, separating the tokens that would merge.
+struct Separated<'a, S> {
+    symbols: &'a S,
+    boundaries: Option<&'a TokenBoundaries<'a>>,
+}
+
+/// Where `Fixpoint` keeps the resolutions of the variables it resolves.
+pub(crate) trait VariableResolutions: SymbolLookup {
+    /// The resolution of the variable at `index` so far.
+    fn variable(&self, index: usize, variable: &Variable) -> Option<&Arc<Resolution>>;
+    fn set_variable(&mut self, index: usize, variable: &Variable, resolution: Arc<Resolution>);
+}
+
+/// Resolves variables to their cheapest examples, going by the variables that refer to each
+/// other.
+pub(crate) struct Fixpoint<'a> {
+    variables: &'a [Variable],
+    /// For every variable, the indices of the variables whose rules reference it.
+    dependents: Vec<Vec<usize>>,
+    cost_model: CostModel,
+    pattern_matches: &'a HashMap<String, String>,
+    config: &'a Config,
+    boundaries: Option<&'a TokenBoundaries<'a>>,
+}
+
+impl Resolution {
+    pub fn new(cost_model: CostModel, derivation: Derivation, metrics: Metrics) -> Self {
+        Resolution {
+            edges: TokenEdges::of(&derivation),
+            derivation,
+            metrics,
+            cost: cost_model.cost(metrics),
+        }
+    }
+
+    pub fn text(text: &str, cost_model: CostModel) -> Self {
+        Resolution::new(
+            cost_model,
+            Derivation::Text(text.to_string()),
+            Metrics::token(text),
+        )
+    }
+
+    /// Marks the resolution as the example of the token at `index` in the lexical grammar.
+    pub(crate) fn with_token(mut self, index: usize) -> Self {
+        self.edges = TokenEdges::token(Some(index), &self.derivation.to_string());
+        self
+    }
+
+    /// Resolutions are ordered by cost, and where the cost is the same by the length of their text
+    /// and then the text itself. Those that leave out fewer repetitions come first regardless.
+    fn is_cheaper_than(&self, other: &Resolution) -> bool {
+        let key = |resolution: &Resolution| {
+            let metrics = resolution.metrics;
+            (metrics.omitted_repeats, resolution.cost, metrics.length)
+        };
+        key(self)
+            .cmp(&key(other))
+            .then_with(|| {
+                self.derivation
+                    .to_string()
+                    .cmp(&other.derivation.to_string())
+            })
+            .is_lt()
+    }
+}
+
+impl SymbolTable {
+    pub fn get(&self, symbol: Symbol) -> Option<&SymbolEntry> {
+        match symbol.kind {
+            SymbolType::NonTerminal => self.non_terminals.get(symbol.index),
+            SymbolType::Terminal => self.terminals.get(symbol.index),
+            SymbolType::External => self.externals.get(symbol.index),
+            SymbolType::End | SymbolType::EndOfNonTerminalExtra => None,
+        }
+    }
+}
+
+impl SymbolEntry {
+    pub fn new(name: &str, kind: VariableType, resolution: Option<Arc<Resolution>>) -> Self {
+        SymbolEntry {
+            name: name.to_string(),
+            kind,
+            resolution,
+        }
+    }
+
+    /// Whether the symbol shows up as a node in the syntax tree.
+    pub fn is_visible(&self) -> bool {
+        self.kind.is_visible()
+    }
+}
+
+impl SymbolLookup for SymbolResolutions {
+    fn named(&self, name: &str) -> Option<&Arc<Resolution>> {
+        self.get(name)
+    }
+
+    fn interned(&self, _symbol: Symbol) -> Option<&SymbolEntry> {
+        None
+    }
+}
+
+impl SymbolLookup for SymbolTable {
+    fn named(&self, _name: &str) -> Option<&Arc<Resolution>> {
+        None
+    }
+
+    fn interned(&self, symbol: Symbol) -> Option<&SymbolEntry> {
+        self.get(symbol)
+    }
+}
+
+impl VariableResolutions for SymbolResolutions {
+    fn variable(&self, _index: usize, variable: &Variable) -> Option<&Arc<Resolution>> {
+        self.get(&variable.name)
+    }
+
+    fn set_variable(&mut self, _index: usize, variable: &Variable, resolution: Arc<Resolution>) {
+        self.insert(variable.name.clone(), resolution);
+    }
+}
+
+impl VariableResolutions for SymbolTable {
+    fn variable(&self, index: usize, _variable: &Variable) -> Option<&Arc<Resolution>> {
+        self.non_terminals.get(index)?.resolution.as_ref()
+    }
+
+    fn set_variable(&mut self, index: usize, _variable: &Variable, resolution: Arc<Resolution>) {
+        self.non_terminals[index].resolution = Some(resolution);
+    }
+}
+
+impl<S: SymbolLookup> SymbolLookup for VariableLookup<'_, S> {
+    fn named(&self, name: &str) -> Option<&Arc<Resolution>> {
+        self.symbols.named(name)
+    }
+
+    fn interned(&self, symbol: Symbol) -> Option<&SymbolEntry> {
+        self.symbols.interned(symbol)
+    }
+
+    fn separator(&self, left: &TokenEdge, right: &TokenEdge) -> Option<&str> {
+        self.symbols.separator(left, right)
+    }
+
+    fn repeat_count(&self) -> RepeatCount {
+        self.repeat_count
+    }
+
+    fn separates_tokens(&self) -> bool {
+        self.symbols.separates_tokens()
+    }
+}
+
+impl<S: SymbolLookup> SymbolLookup for Separated<'_, S> {
+    fn named(&self, name: &str) -> Option<&Arc<Resolution>> {
+        self.symbols.named(name)
+    }
+
+    fn interned(&self, symbol: Symbol) -> Option<&SymbolEntry> {
+        self.symbols.interned(symbol)
+    }
+
+    fn separator(&self, left: &TokenEdge, right: &TokenEdge) -> Option<&str> {
+        self.boundaries?.separator(left, right)
+    }
+
+    fn separates_tokens(&self) -> bool {
+        self.boundaries.is_some()
+    }
+}
+
+pub(crate) fn resolve_rule<S: SymbolLookup>(
+    cost_model: CostModel,
+    pattern_matches: &HashMap<String, String>,
+    symbol_resolutions: &S,
+    rule: &Rule,
+) -> Option<Resolution> {
+    match rule {
+        Rule::Blank => Some(Resolution::text("", cost_model)),
+        Rule::String(s) => Some(Resolution::text(s, cost_model)),
+        Rule::Pattern(s) => pattern_matches
+            .get(s)
+            .map(|str| Resolution::text(str, cost_model)),
+        Rule::Metadata { params, rule } => {
+            let resolution = resolve_rule(cost_model, pattern_matches, symbol_resolutions, rule)?;
+            Some(Resolution::new(
+                cost_model,
+                Derivation::Metadata {
+                    params: params.clone(),
+                    derivation: Box::new(resolution.derivation),
+                },
+                metadata_metrics(params, resolution.metrics),
+            ))
+        }
+
+        Rule::Repeat(rule) => {
+            let resolution = resolve_rule(cost_model, pattern_matches, symbol_resolutions, rule)?;
+            let copies = if symbol_resolutions.separates_tokens() {
+                symbol_resolutions.repeat_count().copies()
+            } else {
+                1
+            };
+            Some(concat(
+                cost_model,
+                symbol_resolutions,
+                vec![resolution; copies],
+            ))
+        }
+        Rule::Seq(rules) => {
+            let resolutions = rules
+                .iter()
+                .map(|rule| resolve_rule(cost_model, pattern_matches, symbol_resolutions, rule))
+                .collect::<Option<Vec<_>>>()?;
+            Some(concat(cost_model, symbol_resolutions, resolutions))
+        }
+        Rule::Choice(rules) => {
+            // `repeat` is a choice between `repeat1` and blank, and an optional list a choice
+            // between blank and a sequence with a repetition. A fixed number of copies rules out
+            // blank unless the repetition doesn't resolve.
+            let omits_repeat = matches!(symbol_resolutions.repeat_count(), RepeatCount::Fixed(_))
+                && rules.iter().any(contains_repeat);
+            rules
+                .iter()
+                .filter_map(|rule| {
+                    let resolution =
+                        resolve_rule(cost_model, pattern_matches, symbol_resolutions, rule)?;
+                    if omits_repeat && *rule == Rule::Blank {
+                        return Some(omitted_repeat(cost_model, resolution));
+                    }
+                    Some(resolution)
+                })
+                .reduce(|cheapest, resolution| {
+                    if resolution.is_cheaper_than(&cheapest) {
+                        resolution
+                    } else {
+                        cheapest
+                    }
+                })
+        }
+
+        Rule::NamedSymbol(name) => {
+            let resolution = symbol_resolutions.named(name)?;
+            Some(Resolution::new(
+                cost_model,
+                Derivation::Symbol {
+                    name: name.clone(),
+                    resolution: resolution.clone(),
+                },
+                symbol_metrics(!name.starts_with('_'), resolution),
+            ))
+        }
+        Rule::Symbol(symbol) => {
+            let entry = symbol_resolutions.interned(*symbol)?;
+            let resolution = entry.resolution.as_ref()?;
+            Some(Resolution::new(
+                cost_model,
+                Derivation::Symbol {
+                    name: entry.name.clone(),
+                    resolution: resolution.clone(),
+                },
+                // An anonymous symbol is a token, whose node its metrics already count.
+                symbol_metrics(entry.kind == VariableType::Named, resolution),
+            ))
+        }
+    }
+}
+
+/// Whether a rule repeats something, without looking into the symbols it refers to.
+fn contains_repeat(rule: &Rule) -> bool {
+    match rule {
+        Rule::Repeat(_) => true,
+        Rule::Metadata { rule, .. } => contains_repeat(rule),
+        Rule::Seq(rules) | Rule::Choice(rules) => rules.iter().any(contains_repeat),
+        _ => false,
+    }
+}
+
+/// Marks a resolution as leaving out a repetition.
+fn omitted_repeat(cost_model: CostModel, resolution: Resolution) -> Resolution {
+    let metrics = Metrics {
+        omitted_repeats: resolution.metrics.omitted_repeats + 1,
+        ..resolution.metrics
+    };
+    Resolution::new(cost_model, resolution.derivation, metrics)
+}
+
+/// Puts resolutions one after the other, separating the tokens that would merge.
+pub(crate) fn concat<S: SymbolLookup>(
+    cost_model: CostModel,
+    symbol_resolutions: &S,
+    resolutions: Vec<Resolution>,
+) -> Resolution {
+    let mut derivations = vec![];
+    let mut metrics = Metrics::default();
+    let mut last_token: Option<TokenEdge> = None;
+    for resolution in resolutions {
+        if let (Some(left), Some(right)) = (&last_token, &resolution.edges.first) {
+            if let Some(separator) = symbol_resolutions.separator(left, right) {
+                derivations.push(Derivation::Separator(separator.to_string()));
+                metrics.length += separator.chars().count();
+            }
+        }
+        if resolution.edges.last.is_some() {
+            last_token = resolution.edges.last;
+        }
+        derivations.push(resolution.derivation);
+        metrics = metrics + resolution.metrics;
+    }
+    Resolution::new(cost_model, Derivation::Seq(derivations), metrics)
+}
+
+pub(crate) fn metadata_metrics(params: &MetadataParams, metrics: Metrics) -> Metrics {
+    if params.is_token {
+        token_metrics(metrics)
+    } else {
+        metrics
+    }
+}
+
+/// Everything inside of a token counts as that one token.
+fn token_metrics(metrics: Metrics) -> Metrics {
+    let tokens = usize::from(metrics.length > 0);
+    Metrics {
+        length: metrics.length,
+        tokens,
+        nodes: tokens,
+        omitted_repeats: 0,
+    }
+}
+
+/// Resolves the rule of a token from the lexical grammar, which can't refer to any symbols.
+pub(crate) fn resolve_token(
+    cost_model: CostModel,
+    pattern_matches: &HashMap<String, String>,
+    rule: &Rule,
+) -> Option<Resolution> {
+    let resolution = resolve_rule(cost_model, pattern_matches, &SymbolTable::default(), rule)?;
+    Some(Resolution::new(
+        cost_model,
+        resolution.derivation,
+        token_metrics(resolution.metrics),
+    ))
+}
+
+/// Hidden variables don't show up as nodes in the syntax tree.
+pub(crate) fn symbol_metrics(is_visible: bool, resolution: &Resolution) -> Metrics {
+    let nodes = usize::from(is_visible);
+    resolution.metrics
+        + Metrics {
+            nodes,
+            ..Metrics::default()
+        }
+}
+
+/// Resolves every variable of the grammar to its cheapest example, looking up the symbols its
+/// rules refer to by name.
+pub(crate) fn resolve_grammar(
+    grammar: &InputGrammar,
+    cost_model: CostModel,
+    pattern_matches: &HashMap<String, String>,
+    config: &Config,
+) -> SymbolResolutions {
+    let mut symbol_resolutions = config.symbol_resolutions(cost_model);
+    Fixpoint::new(
+        &grammar.variables,
+        cost_model,
+        pattern_matches,
+        config,
+        None,
+    )
+    .resolve(&mut symbol_resolutions);
+    symbol_resolutions
+}
+
+/// Resolves the non-terminals of an interned grammar like `resolve_grammar` does, looking up the
+/// symbols their rules refer to in `symbol_table`. Its terminals and externals need to be filled
+/// in already, while its non-terminals are replaced by the resolved `variables`.
+pub fn resolve_symbol_table(
+    variables: &[Variable],
+    cost_model: CostModel,
+    pattern_matches: &HashMap<String, String>,
+    config: &Config,
+    symbol_table: SymbolTable,
+) -> SymbolTable {
+    resolve_separated_symbol_table(
+        variables,
+        cost_model,
+        pattern_matches,
+        config,
+        symbol_table,
+        None,
+    )
+}
+
+/// Resolves the non-terminals like `resolve_symbol_table`, putting separators between the tokens
+/// that `boundaries` finds would merge.
+pub(crate) fn resolve_separated_symbol_table(
+    variables: &[Variable],
+    cost_model: CostModel,
+    pattern_matches: &HashMap<String, String>,
+    config: &Config,
+    mut symbol_table: SymbolTable,
+    boundaries: Option<&TokenBoundaries>,
+) -> SymbolTable {
+    symbol_table.non_terminals = non_terminal_entries(variables, cost_model, config);
+    Fixpoint::new(variables, cost_model, pattern_matches, config, boundaries)
+        .resolve(&mut symbol_table);
+    symbol_table
+}
+
+/// Entries for the non-terminals of an interned grammar, with the examples of the pinned ones.
+pub(crate) fn non_terminal_entries(
+    variables: &[Variable],
+    cost_model: CostModel,
+    config: &Config,
+) -> Vec<SymbolEntry> {
+    variables
+        .iter()
+        .map(|variable| {
+            let pinned = config
+                .variables
+                .get(&variable.name)
+                .map(|example| Arc::new(Resolution::text(example, cost_model)));
+            SymbolEntry::new(&variable.name, variable.kind, pinned)
+        })
+        .collect()
+}
+
+impl<'a> Fixpoint<'a> {
+    pub(crate) fn new(
+        variables: &'a [Variable],
+        cost_model: CostModel,
+        pattern_matches: &'a HashMap<String, String>,
+        config: &'a Config,
+        boundaries: Option<&'a TokenBoundaries<'a>>,
+    ) -> Self {
+        Fixpoint {
+            variables,
+            dependents: reverse_dependencies(variables),
+            cost_model,
+            pattern_matches,
+            config,
+            boundaries,
+        }
+    }
+
+    pub(crate) fn variables(&self) -> &'a [Variable] {
+        self.variables
+    }
+
+    pub(crate) fn dependents(&self) -> &[Vec<usize>] {
+        &self.dependents
+    }
+
+    /// Resolves all variables, storing their examples in `symbols`.
+    pub(crate) fn resolve<S: VariableResolutions>(&self, symbols: &mut S) {
+        self.resolve_some(&(0..self.variables.len()).collect(), symbols);
+    }
+
+    /// Resolves the variables at `indices`, whose rules may refer to each other. The variables
+    /// they refer to otherwise need to be resolved in `symbols` already.
+    ///
+    /// Instead of re-evaluating all variables until nothing changes, a variable is only
+    /// re-evaluated once a variable it references got cheaper. Variables pinned in the config keep
+    /// their example.
+    pub(crate) fn resolve_some<S: VariableResolutions>(
+        &self,
+        indices: &BTreeSet<usize>,
+        symbols: &mut S,
+    ) {
+        let mut worklist = indices.clone();
+        // A variable is only updated to a strictly cheaper resolution. The order of resolutions
+        // has no infinite descending chains, as costs are natural numbers and there are only
+        // finitely many texts of a length, so every variable is updated finitely often.
+        while let Some(index) = worklist.pop_first() {
+            let variable = &self.variables[index];
+            if self.config.is_pinned(&variable.name) {
+                continue;
+            }
+            let lookup = Separated {
+                symbols: &*symbols,
+                boundaries: self.boundaries,
+            };
+            let Some(resolution) = resolve_in_variable(
+                self.cost_model,
+                self.pattern_matches,
+                self.config,
+                &lookup,
+                variable,
+                &variable.rule,
+            ) else {
+                continue;
+            };
+            if symbols
+                .variable(index, variable)
+                .is_some_and(|previous| !resolution.is_cheaper_than(previous))
+            {
+                continue;
+            }
+
+            symbols.set_variable(index, variable, Arc::new(resolution));
+            worklist.extend(
+                self.dependents[index]
+                    .iter()
+                    .filter(|dependent| indices.contains(dependent)),
+            );
+        }
+    }
+}
+
+/// Resolves `rule`, which is part of the rule of `variable`, the way
+/// `resolve_separated_symbol_table` resolves whole variables.
+pub(crate) fn resolve_variable_rule(
+    cost_model: CostModel,
+    pattern_matches: &HashMap<String, String>,
+    config: &Config,
+    symbol_table: &SymbolTable,
+    boundaries: Option<&TokenBoundaries>,
+    variable: &Variable,
+    rule: &Rule,
+) -> Option<Resolution> {
+    let symbols = Separated {
+        symbols: symbol_table,
+        boundaries,
+    };
+    resolve_in_variable(
+        cost_model,
+        pattern_matches,
+        config,
+        &symbols,
+        variable,
+        rule,
+    )
+}
+
+/// Resolves `rule`, which is part of the rule of `variable`, with the repeat count the config
+/// sets for that variable.
+pub(crate) fn resolve_in_variable<S: SymbolLookup>(
+    cost_model: CostModel,
+    pattern_matches: &HashMap<String, String>,
+    config: &Config,
+    symbols: &S,
+    variable: &Variable,
+    rule: &Rule,
+) -> Option<Resolution> {
+    let lookup = VariableLookup {
+        symbols,
+        repeat_count: config.repeat_count(&variable.name),
+    };
+    resolve_rule(cost_model, pattern_matches, &lookup, rule)
+}
+
+/// For every variable, the indices of the variables whose rules reference it, either by name or
+/// as an interned non-terminal.
+pub(crate) fn reverse_dependencies(variables: &[Variable]) -> Vec<Vec<usize>> {
+    let indices: HashMap<&str, usize> = variables
+        .iter()
+        .enumerate()
+        .map(|(index, variable)| (variable.name.as_str(), index))
+        .collect();
+
+    let mut dependents = vec![vec![]; variables.len()];
+    for (index, variable) in variables.iter().enumerate() {
+        let mut dependencies = vec![];
+        collect_dependencies(&variable.rule, &indices, &mut dependencies);
+        for dependency in dependencies {
+            if dependency < variables.len() && !dependents[dependency].contains(&index) {
+                dependents[dependency].push(index);
+            }
+        }
+    }
+    dependents
+}
+
+fn collect_dependencies(
+    rule: &Rule,
+    indices: &HashMap<&str, usize>,
+    dependencies: &mut Vec<usize>,
+) {
+    match rule {
+        Rule::NamedSymbol(name) => dependencies.extend(indices.get(name.as_str())),
+        Rule::Symbol(symbol) if symbol.kind == SymbolType::NonTerminal => {
+            dependencies.push(symbol.index)
+        }
+        Rule::Metadata { rule, .. } | Rule::Repeat(rule) => {
+            collect_dependencies(rule, indices, dependencies)
+        }
+        Rule::Choice(rules) | Rule::Seq(rules) => {
+            for rule in rules {
+                collect_dependencies(rule, indices, dependencies);
+            }
+        }
+        Rule::Blank | Rule::String(_) | Rule::Pattern(_) | Rule::Symbol(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree_sitter_cli::{
+        parse_grammar::parse_grammar,
+        rules::{Alias, Precedence},
+    };
+
+    #[test]
+    fn test_resolve_metadata() {
+        let rule = Rule::seq(vec![
+            Rule::field(
+                "name".to_string(),
+                Rule::alias(Rule::named("identifier"), "name".to_string(), true),
+            ),
+            Rule::prec(
+                Precedence::Integer(1),
+                Rule::token(Rule::seq(vec![Rule::string("="), Rule::string(">")])),
+            ),
+        ]);
+        let symbol_resolutions = SymbolResolutions::from([(
+            "identifier".to_string(),
+            Arc::new(Resolution::text("a", CostModel::Length)),
+        )]);
+
+        let resolution = resolve_rule(
+            CostModel::Length,
+            &HashMap::new(),
+            &symbol_resolutions,
+            &rule,
+        )
+        .unwrap();
+        assert_eq!(resolution.derivation.to_string(), "a=>");
+        assert_eq!(
+            resolution.metrics,
+            Metrics {
+                length: 3,
+                tokens: 2,
+                nodes: 3,
+                omitted_repeats: 0
+            }
+        );
+
+        let derivation = resolution.derivation;
+        let Derivation::Seq(children) = derivation else {
+            panic!("expected a sequence, got {:?}", derivation);
+        };
+        let Derivation::Metadata { params, derivation } = &children[0] else {
+            panic!("expected metadata, got {:?}", children[0]);
+        };
+        assert_eq!(params.field_name.as_deref(), Some("name"));
+        assert_eq!(
+            params.alias,
+            Some(Alias {
+                value: "name".to_string(),
+                is_named: true
+            })
+        );
+        assert!(matches!(
+            derivation.as_ref(),
+            Derivation::Symbol { name, .. } if name == "identifier"
+        ));
+
+        let Derivation::Metadata { params, derivation } = &children[1] else {
+            panic!("expected metadata, got {:?}", children[1]);
+        };
+        assert_eq!(params.precedence, Precedence::Integer(1));
+        assert!(matches!(
+            derivation.as_ref(),
+            Derivation::Metadata { params, .. } if params.is_token
+        ));
+    }
+
+    #[test]
+    fn test_resolve_grammar() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "calc",
+            "rules": {
+                "program": {
+                    "type": "REPEAT",
+                    "content": { "type": "SYMBOL", "name": "statement" }
+                },
+                "statement": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "SYMBOL", "name": "expression" },
+                        { "type": "STRING", "value": ";" }
+                    ]
+                },
+                "expression": {
+                    "type": "CHOICE",
+                    "members": [
+                        { "type": "SYMBOL", "name": "binary" },
+                        { "type": "SYMBOL", "name": "number" }
+                    ]
+                },
+                "binary": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "SYMBOL", "name": "expression" },
+                        { "type": "STRING", "value": "+" },
+                        { "type": "SYMBOL", "name": "expression" }
+                    ]
+                },
+                "number": { "type": "PATTERN", "value": "\\d+" },
+                "unreachable": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "SYMBOL", "name": "unreachable" },
+                        { "type": "STRING", "value": "!" }
+                    ]
+                }
+            }
+        }"#,
+        )
+        .unwrap();
+        let pattern_matches = HashMap::from([("\\d+".to_string(), "1".to_string())]);
+
+        let examples: HashMap<String, String> = resolve_grammar(
+            &grammar,
+            CostModel::Length,
+            &pattern_matches,
+            &Config::default(),
+        )
+        .into_iter()
+        .map(|(name, resolution)| (name, resolution.derivation.to_string()))
+        .collect();
+        assert_eq!(
+            examples,
+            HashMap::from([
+                ("program".to_string(), "".to_string()),
+                ("statement".to_string(), "1;".to_string()),
+                ("expression".to_string(), "1".to_string()),
+                ("binary".to_string(), "1+1".to_string()),
+                ("number".to_string(), "1".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_resolve_choice_by_cost() {
+        let rule = Rule::choice(vec![
+            Rule::seq(vec![
+                Rule::string("("),
+                Rule::string("b"),
+                Rule::string(")"),
+            ]),
+            Rule::seq(vec![Rule::string("a"), Rule::string("b")]),
+            Rule::string("ccc"),
+        ]);
+        let table = [
+            (CostModel::Length, "ab"),
+            (CostModel::Tokens, "ccc"),
+            (
+                CostModel::Weighted {
+                    length: 2,
+                    tokens: 1,
+                    nodes: 0,
+                },
+                "ab",
+            ),
+        ];
+        for (cost_model, expected) in table {
+            let resolution = resolve_rule(
+                cost_model,
+                &HashMap::new(),
+                &SymbolResolutions::new(),
+                &rule,
+            )
+            .unwrap();
+            assert_eq!(resolution.derivation.to_string(), expected);
+            assert_eq!(resolution.cost, cost_model.cost(resolution.metrics));
+        }
+    }
+
+    #[test]
+    #[ntest::timeout(1000)]
+    fn test_resolve_grammar_without_costs() {
+        // Everything costs nothing, so only the texts tell the examples of `a` apart, which get
+        // ever smaller alphabetically as more `a`s are put in front of the `b`.
+        let grammar = parse_grammar(
+            r#"{
+            "name": "free",
+            "rules": {
+                "a": {
+                    "type": "CHOICE",
+                    "members": [
+                        { "type": "STRING", "value": "b" },
+                        {
+                            "type": "SEQ",
+                            "members": [
+                                { "type": "STRING", "value": "a" },
+                                { "type": "SYMBOL", "name": "a" }
+                            ]
+                        }
+                    ]
+                }
+            }
+        }"#,
+        )
+        .unwrap();
+        let cost_model = CostModel::Weighted {
+            length: 0,
+            tokens: 0,
+            nodes: 0,
+        };
+
+        let symbol_resolutions =
+            resolve_grammar(&grammar, cost_model, &HashMap::new(), &Config::default());
+        assert_eq!(symbol_resolutions["a"].derivation.to_string(), "b");
+    }
+
+    #[test]
+    fn test_resolve_grammar_with_config() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "config",
+            "externals": [{ "type": "SYMBOL", "name": "heredoc" }],
+            "rules": {
+                "program": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "SYMBOL", "name": "identifier" },
+                        { "type": "SYMBOL", "name": "heredoc" }
+                    ]
+                },
+                "identifier": { "type": "PATTERN", "value": "[a-z]+" }
+            }
+        }"#,
+        )
+        .unwrap();
+        let config = Config {
+            variables: HashMap::from([("identifier".to_string(), "x".to_string())]),
+            externals: HashMap::from([("heredoc".to_string(), "<<EOF".to_string())]),
+            ..Config::default()
+        };
+
+        let symbol_resolutions =
+            resolve_grammar(&grammar, CostModel::Length, &HashMap::new(), &config);
+        assert_eq!(
+            symbol_resolutions["program"].derivation.to_string(),
+            "x<<EOF"
+        );
+    }
+
+    #[test]
+    fn test_resolve_repeat_count() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "lists",
+            "rules": {
+                "program": {
+                    "type": "REPEAT",
+                    "content": { "type": "SYMBOL", "name": "list" }
+                },
+                "list": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "STRING", "value": "[" },
+                        { "type": "SYMBOL", "name": "number" },
+                        {
+                            "type": "REPEAT",
+                            "content": {
+                                "type": "SEQ",
+                                "members": [
+                                    { "type": "STRING", "value": "," },
+                                    { "type": "SYMBOL", "name": "number" }
+                                ]
+                            }
+                        },
+                        { "type": "STRING", "value": "]" }
+                    ]
+                },
+                "digits": {
+                    "type": "REPEAT1",
+                    "content": { "type": "SYMBOL", "name": "number" }
+                },
+                "number": { "type": "PATTERN", "value": "\\d" }
+            }
+        }"#,
+        )
+        .unwrap();
+        let pattern_matches = HashMap::from([("\\d".to_string(), "1".to_string())]);
+        let examples = |config: &Config| {
+            let symbol_resolutions =
+                resolve_grammar(&grammar, CostModel::Length, &pattern_matches, config);
+            ["program", "list", "digits"]
+                .map(|name| symbol_resolutions[name].derivation.to_string())
+        };
+
+        assert_eq!(examples(&Config::default()), ["", "[1]", "1"]);
+        let config = Config {
+            repeat: RepeatCount::Fixed(1),
+            ..Config::default()
+        };
+        assert_eq!(examples(&config), ["[1,1]", "[1,1]", "1"]);
+        let config = Config {
+            repeat: RepeatCount::Fixed(2),
+            repeats: HashMap::from([("program".to_string(), RepeatCount::Minimum)]),
+            ..Config::default()
+        };
+        // Without token boundaries, copies are left out rather than risk merging, see
+        // `test_repeat_copies_are_separated` in the resolver for more than one copy.
+        assert_eq!(examples(&config), ["", "[1,1]", "1"]);
+    }
+
+    #[test]
+    fn test_resolve_symbol_table() {
+        // statement: seq(expression, terminal ";"), expression: choice(identifier, external)
+        let variables = vec![
+            Variable {
+                name: "statement".to_string(),
+                kind: VariableType::Named,
+                rule: Rule::seq(vec![Rule::non_terminal(1), Rule::terminal(0)]),
+            },
+            Variable {
+                name: "_expression".to_string(),
+                kind: VariableType::Hidden,
+                rule: Rule::choice(vec![Rule::terminal(1), Rule::external(0)]),
+            },
+        ];
+        let text = |text| Some(Arc::new(Resolution::text(text, CostModel::Length)));
+        let symbol_table = SymbolTable {
+            terminals: vec![
+                SymbolEntry::new(";", VariableType::Anonymous, text(";")),
+                SymbolEntry::new("identifier", VariableType::Named, text("abc")),
+            ],
+            externals: vec![SymbolEntry::new(
+                "heredoc",
+                VariableType::Named,
+                text("<<A"),
+            )],
+            ..SymbolTable::default()
+        };
+
+        let symbol_table = resolve_symbol_table(
+            &variables,
+            CostModel::Length,
+            &HashMap::new(),
+            &Config::default(),
+            symbol_table,
+        );
+        let statement = symbol_table
+            .get(Symbol::non_terminal(0))
+            .and_then(|entry| entry.resolution.as_ref())
+            .unwrap();
+        assert_eq!(statement.derivation.to_string(), "<<A;");
+        // `heredoc` is a node on top of its token, while `;` is only the token itself.
+        assert_eq!(
+            statement.metrics,
+            Metrics {
+                length: 4,
+                tokens: 2,
+                nodes: 3,
+                omitted_repeats: 0
+            }
+        );
+        let Derivation::Seq(children) = &statement.derivation else {
+            panic!("expected a sequence, got {:?}", statement.derivation);
+        };
+        assert!(matches!(
+            &children[0],
+            Derivation::Symbol { name, .. } if name == "_expression"
+        ));
+        assert_eq!(symbol_table.get(Symbol::end()), None);
+    }
+}
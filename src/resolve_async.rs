@@ -0,0 +1,305 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap},
+    future::{poll_fn, Future},
+    pin::Pin,
+    sync::Arc,
+    task::Poll,
+};
+
+use crate::{
+    config::Config,
+    cost::CostModel,
+    eventually::Eventually,
+    resolve::{
+        non_terminal_entries, Fixpoint, Resolution, SymbolResolutions, SymbolTable,
+        VariableResolutions,
+    },
+    token_boundaries::TokenBoundaries,
+    tree_sitter_cli::grammars::{InputGrammar, Variable},
+};
+
+/// Resolves every variable of the grammar like `resolve_grammar`, but with a future for every
+/// group of variables that refer to each other.
+pub(crate) async fn resolve_grammar_async(
+    grammar: &InputGrammar,
+    cost_model: CostModel,
+    pattern_matches: &HashMap<String, String>,
+    config: &Config,
+) -> SymbolResolutions {
+    let fixpoint = Fixpoint::new(
+        &grammar.variables,
+        cost_model,
+        pattern_matches,
+        config,
+        None,
+    );
+    resolve_async(&fixpoint, config.symbol_resolutions(cost_model)).await
+}
+
+/// Resolves the non-terminals of an interned grammar like `resolve_separated_symbol_table`, but
+/// with a future for every group of variables that refer to each other.
+pub(crate) async fn resolve_separated_symbol_table_async(
+    variables: &[Variable],
+    cost_model: CostModel,
+    pattern_matches: &HashMap<String, String>,
+    config: &Config,
+    mut symbol_table: SymbolTable,
+    boundaries: &TokenBoundaries<'_>,
+) -> SymbolTable {
+    symbol_table.non_terminals = non_terminal_entries(variables, cost_model, config);
+    let fixpoint = Fixpoint::new(
+        variables,
+        cost_model,
+        pattern_matches,
+        config,
+        Some(boundaries),
+    );
+    resolve_async(&fixpoint, symbol_table).await
+}
+
+/// Resolves the variables of `fixpoint` into `symbols`, with the same examples as
+/// `Fixpoint::resolve`.
+///
+/// Variables that refer to each other are resolved together, by a future that waits for the
+/// variables they refer to outside of the group to be published first. Waiting on single variables
+/// instead would wait forever on recursive ones, and would have to settle for whichever
+/// alternative of a choice resolves first rather than the cheapest one.
+pub(crate) async fn resolve_async<S: VariableResolutions>(
+    fixpoint: &Fixpoint<'_>,
+    symbols: S,
+) -> S {
+    let variables = fixpoint.variables();
+    let dependents = fixpoint.dependents();
+    let mut dependencies = vec![BTreeSet::new(); variables.len()];
+    for (index, dependents) in dependents.iter().enumerate() {
+        for dependent in dependents {
+            dependencies[*dependent].insert(index);
+        }
+    }
+
+    let resolutions: Vec<Eventually<Option<Arc<Resolution>>>> =
+        variables.iter().map(|_| Eventually::new()).collect();
+    let symbols = RefCell::new(symbols);
+    let components = strongly_connected_components(dependents);
+    join_all(components.iter().map(|component| {
+        let dependencies = &dependencies;
+        let resolutions = &resolutions;
+        let symbols = &symbols;
+        async move {
+            for index in component {
+                for dependency in dependencies[*index].difference(component) {
+                    resolutions[*dependency].read().await;
+                }
+            }
+
+            let published: Vec<_> = {
+                let mut symbols = symbols.borrow_mut();
+                fixpoint.resolve_some(component, &mut *symbols);
+                component
+                    .iter()
+                    .map(|index| symbols.variable(*index, &variables[*index]).cloned())
+                    .collect()
+            };
+            for (index, resolution) in component.iter().zip(published) {
+                resolutions[*index].write(resolution).await;
+            }
+        }
+    }))
+    .await;
+    symbols.into_inner()
+}
+
+/// Polls all futures on the current task until every one of them is done. They borrow from the
+/// caller, so unlike with a `JoinSet` they can't be spawned.
+async fn join_all<F: Future<Output = ()>>(futures: impl Iterator<Item = F>) {
+    let mut futures: Vec<Pin<Box<F>>> = futures.map(Box::pin).collect();
+    poll_fn(|cx| {
+        futures.retain_mut(|future| future.as_mut().poll(cx).is_pending());
+        if futures.is_empty() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Groups the nodes of a graph, given as the edges going out of every node, into the largest
+/// groups in which every node reaches every other one. Uses Tarjan's algorithm.
+fn strongly_connected_components(edges: &[Vec<usize>]) -> Vec<BTreeSet<usize>> {
+    struct Tarjan<'a> {
+        edges: &'a [Vec<usize>],
+        indices: Vec<Option<usize>>,
+        next_index: usize,
+        low_links: Vec<usize>,
+        stack: Vec<usize>,
+        on_stack: Vec<bool>,
+        components: Vec<BTreeSet<usize>>,
+    }
+
+    impl Tarjan<'_> {
+        fn visit(&mut self, node: usize) {
+            let index = self.next_index;
+            self.next_index += 1;
+            self.indices[node] = Some(index);
+            self.low_links[node] = index;
+            self.stack.push(node);
+            self.on_stack[node] = true;
+
+            let edges = self.edges;
+            for &successor in &edges[node] {
+                match self.indices[successor] {
+                    None => {
+                        self.visit(successor);
+                        self.low_links[node] = self.low_links[node].min(self.low_links[successor]);
+                    }
+                    Some(index) if self.on_stack[successor] => {
+                        self.low_links[node] = self.low_links[node].min(index);
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            if self.low_links[node] == index {
+                let mut component = BTreeSet::new();
+                while let Some(member) = self.stack.pop() {
+                    self.on_stack[member] = false;
+                    component.insert(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                self.components.push(component);
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        edges,
+        indices: vec![None; edges.len()],
+        next_index: 0,
+        low_links: vec![0; edges.len()],
+        stack: vec![],
+        on_stack: vec![false; edges.len()],
+        components: vec![],
+    };
+    for node in 0..edges.len() {
+        if tarjan.indices[node].is_none() {
+            tarjan.visit(node);
+        }
+    }
+    tarjan.components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{resolve::resolve_grammar, tree_sitter_cli::parse_grammar::parse_grammar};
+
+    #[tokio::test]
+    #[ntest::timeout(1000)]
+    async fn test_resolve_grammar_async() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "calc",
+            "rules": {
+                "expression": {
+                    "type": "CHOICE",
+                    "members": [
+                        {
+                            "type": "SEQ",
+                            "members": [
+                                { "type": "SYMBOL", "name": "expression" },
+                                { "type": "STRING", "value": "+" },
+                                { "type": "SYMBOL", "name": "expression" }
+                            ]
+                        },
+                        { "type": "SYMBOL", "name": "parenthesized" },
+                        { "type": "SYMBOL", "name": "number" }
+                    ]
+                },
+                "parenthesized": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "STRING", "value": "(" },
+                        { "type": "SYMBOL", "name": "expression" },
+                        { "type": "STRING", "value": ")" }
+                    ]
+                },
+                "number": { "type": "PATTERN", "value": "\\d+" },
+                "left": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "SYMBOL", "name": "right" },
+                        { "type": "STRING", "value": "<" }
+                    ]
+                },
+                "right": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "STRING", "value": ">" },
+                        { "type": "SYMBOL", "name": "left" }
+                    ]
+                },
+                "uses_cycle": {
+                    "type": "CHOICE",
+                    "members": [
+                        { "type": "SYMBOL", "name": "left" },
+                        { "type": "STRING", "value": "-" }
+                    ]
+                }
+            }
+        }"#,
+        )
+        .unwrap();
+        let pattern_matches = HashMap::from([("\\d+".to_string(), "1".to_string())]);
+
+        let symbol_resolutions = resolve_grammar_async(
+            &grammar,
+            CostModel::Length,
+            &pattern_matches,
+            &Config::default(),
+        )
+        .await;
+        assert_eq!(
+            symbol_resolutions,
+            resolve_grammar(
+                &grammar,
+                CostModel::Length,
+                &pattern_matches,
+                &Config::default()
+            )
+        );
+
+        let examples: HashMap<String, String> = symbol_resolutions
+            .into_iter()
+            .map(|(name, resolution)| (name, resolution.derivation.to_string()))
+            .collect();
+        assert_eq!(
+            examples,
+            HashMap::from([
+                ("expression".to_string(), "1".to_string()),
+                ("parenthesized".to_string(), "(1)".to_string()),
+                ("number".to_string(), "1".to_string()),
+                ("uses_cycle".to_string(), "-".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_strongly_connected_components() {
+        // 0 -> 1 -> 2 -> 0, 2 -> 3, 4 -> 4
+        let edges = vec![vec![1], vec![2], vec![0, 3], vec![], vec![4]];
+        let mut components = strongly_connected_components(&edges);
+        components.sort();
+        assert_eq!(
+            components,
+            vec![
+                BTreeSet::from([0, 1, 2]),
+                BTreeSet::from([3]),
+                BTreeSet::from([4]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,1276 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{anyhow, Error, Result};
+use serde::{ser::SerializeMap, ser::SerializeStruct, Serialize, Serializer};
+
+use crate::{
+    alternatives::{alternatives, Alternative, AlternativeTag},
+    config::Config,
+    cost::CostModel,
+    diagnostics::{diagnose, diagnose_interned, Report},
+    keywords::{extract_keywords, sample_word, string_value},
+    pattern_samples::pattern_samples,
+    resolve::{
+        resolve_grammar, resolve_separated_symbol_table, resolve_token, resolve_variable_rule,
+        Resolution, SymbolEntry, SymbolResolutions, SymbolTable,
+    },
+    resolve_async::{resolve_grammar_async, resolve_separated_symbol_table_async},
+    sentences::{Context, SentenceBound, Sentences, Symbols},
+    token_boundaries::{shortest_separator, TokenBoundaries},
+    token_overlaps::{token_overlaps, TokenOverlap},
+    tree_sitter_cli::{
+        grammars::{
+            InlinedProductionMap, InputGrammar, LexicalGrammar, Production, SyntaxGrammar,
+            VariableType,
+        },
+        prepare_grammar::{
+            expand_repeats, expand_tokens, extract_tokens, flatten_grammar, intern_symbols,
+            process_inlines, ExtractedLexicalGrammar, ExtractedSyntaxGrammar, InternedGrammar,
+        },
+        rules::{Rule, Symbol, SymbolType},
+    },
+};
+
+/// Finds a minimal example for every variable of a grammar.
+///
+/// ```no_run
+/// use sapling_sitter::{parse_grammar, Resolver};
+///
+/// let grammar = parse_grammar(&std::fs::read_to_string("src/grammar.json")?)?;
+/// let resolver = Resolver::new(grammar);
+/// for example in resolver.resolve().examples() {
+///     println!("{}: {:?}", example.name, example.text());
+/// }
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Resolver {
+    grammar: InputGrammar,
+    /// `None` if the grammar references undefined symbols. It is then resolved by name, so that
+    /// `Resolutions::diagnose` can point at the undefined references.
+    interned: Option<InternedGrammar>,
+    /// `None` if the grammar could not be interned or its tokens could not be compiled.
+    prepared: Option<PreparedGrammar>,
+    /// Why `prepared` is `None`.
+    preparation_error: Option<Error>,
+    config: Config,
+    cost_model: CostModel,
+}
+
+/// The grammar split into its syntax and its tokens, the way tree-sitter prepares it.
+#[derive(Debug)]
+struct PreparedGrammar {
+    /// The variables left after extracting the tokens, which examples are resolved from.
+    extracted: ExtractedSyntaxGrammar,
+    /// The rules of the terminals of `extracted`.
+    tokens: ExtractedLexicalGrammar,
+    /// The same tokens compiled into one NFA.
+    lexical_grammar: LexicalGrammar,
+    /// The variables of `extracted` flattened into productions.
+    syntax_grammar: SyntaxGrammar,
+    /// The productions of `syntax_grammar` with its inlined variables spliced in.
+    inlines: InlinedProductionMap,
+    /// The indices of the tokens in `lexical_grammar` that the word token lexes as keywords.
+    keywords: Vec<usize>,
+}
+
+/// The example of every variable of a grammar, borrowing the grammar from its `Resolver`.
+#[derive(Debug)]
+pub struct Resolutions<'a> {
+    grammar: &'a InputGrammar,
+    interned: Option<&'a InternedGrammar>,
+    config: &'a Config,
+    cost_model: CostModel,
+    pattern_matches: HashMap<String, String>,
+    pattern_errors: Vec<Error>,
+    warnings: Vec<Error>,
+    symbol_resolutions: SymbolResolutions,
+    /// `None` if the grammar was resolved by name instead of from its prepared form.
+    prepared: Option<PreparedResolutions<'a>>,
+}
+
+/// The examples resolved from a prepared grammar, with what it takes to resolve parts of its
+/// rules the same way.
+#[derive(Debug)]
+struct PreparedResolutions<'a> {
+    grammar: &'a PreparedGrammar,
+    symbol_table: SymbolTable,
+    separator: Option<String>,
+}
+
+/// The outcome of resolving one variable.
+#[derive(Clone, Copy, Debug)]
+pub struct VariableExample<'a> {
+    pub name: &'a str,
+    pub kind: VariableType,
+    pub status: Status,
+    /// `None` if the variable could not be resolved, see `Resolutions::diagnose` for why.
+    pub resolution: Option<&'a Resolution>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Resolved,
+    /// The example was taken from the config instead of resolving the variable's rule.
+    Pinned,
+    Unresolved,
+}
+
+impl Resolver {
+    pub fn new(grammar: InputGrammar) -> Self {
+        let (mut interned, prepared) = match intern_symbols(&grammar) {
+            Ok(interned) => {
+                let prepared = PreparedGrammar::new(interned.clone());
+                (Some(interned), prepared)
+            }
+            Err(error) => (None, Err(error)),
+        };
+        let (prepared, preparation_error) = match prepared {
+            Ok(prepared) => (Some(prepared), None),
+            Err(error) => (None, Some(error)),
+        };
+        if let Some(interned) = &mut interned {
+            interned.hide_inlined_variables();
+        }
+        Resolver {
+            prepared,
+            preparation_error,
+            interned,
+            grammar,
+            config: Config::default(),
+            cost_model: CostModel::default(),
+        }
+    }
+
+    pub fn with_config(self, config: Config) -> Self {
+        Resolver { config, ..self }
+    }
+
+    pub fn with_cost_model(self, cost_model: CostModel) -> Self {
+        Resolver { cost_model, ..self }
+    }
+
+    pub fn grammar(&self) -> &InputGrammar {
+        &self.grammar
+    }
+
+    /// Why tree-sitter would reject the grammar, such as an undefined symbol. The grammar is then
+    /// resolved by name, without separating tokens that would merge, repeating content or avoiding
+    /// keywords.
+    pub fn preparation_error(&self) -> Option<&Error> {
+        self.preparation_error.as_ref()
+    }
+
+    /// The grammar the way tree-sitter builds its parser from: every variable flattened into a
+    /// list of productions, with repetitions moved into auxiliary variables and the tokens into a
+    /// lexical grammar. `None` if tree-sitter would reject the grammar.
+    pub fn syntax_grammar(&self) -> Option<&SyntaxGrammar> {
+        self.prepared
+            .as_ref()
+            .map(|prepared| &prepared.syntax_grammar)
+    }
+
+    /// The name of a symbol in the productions of `syntax_grammar`.
+    pub fn symbol_name(&self, symbol: Symbol) -> Option<&str> {
+        let prepared = self.prepared.as_ref()?;
+        let name = match symbol.kind {
+            SymbolType::NonTerminal => &prepared.syntax_grammar.variables.get(symbol.index)?.name,
+            SymbolType::Terminal => &prepared.lexical_grammar.variables.get(symbol.index)?.name,
+            SymbolType::External => {
+                &prepared
+                    .syntax_grammar
+                    .external_tokens
+                    .get(symbol.index)?
+                    .name
+            }
+            SymbolType::End | SymbolType::EndOfNonTerminalExtra => return None,
+        };
+        Some(name)
+    }
+
+    /// The productions tree-sitter uses in place of `production` when the symbol at `step_index`
+    /// is an inlined variable. `None` if there is nothing to inline at that step.
+    pub fn inlined_productions<'a>(
+        &'a self,
+        production: &Production,
+        step_index: u32,
+    ) -> Option<impl Iterator<Item = &'a Production> + 'a> {
+        self.prepared
+            .as_ref()?
+            .inlines
+            .inlined_productions(production, step_index)
+    }
+
+    /// Every pair of tokens that can match the same string, with the one the lexer picks. `None`
+    /// if tree-sitter would reject the grammar.
+    pub fn token_overlaps(&self) -> Option<Vec<TokenOverlap>> {
+        let prepared = self.prepared.as_ref()?;
+        Some(token_overlaps(&prepared.lexical_grammar))
+    }
+
+    /// The strings tree-sitter lexes as keywords instead of as the grammar's word token, such as
+    /// `if` for an `identifier`. Examples of the word token never collide with them.
+    pub fn keywords(&self) -> Vec<&str> {
+        let Some(prepared) = &self.prepared else {
+            return vec![];
+        };
+        prepared
+            .keywords
+            .iter()
+            .filter_map(|i| string_value(&prepared.tokens.variables[*i].rule))
+            .collect()
+    }
+
+    pub fn resolve(&self) -> Resolutions<'_> {
+        let (pattern_matches, pattern_errors) = self.pattern_matches();
+        let Some(prepared) = &self.prepared else {
+            let symbol_resolutions = resolve_grammar(
+                &self.grammar,
+                self.cost_model,
+                &pattern_matches,
+                &self.config,
+            );
+            return self.resolutions(pattern_matches, pattern_errors, symbol_resolutions);
+        };
+        let mut warnings = vec![];
+        let (symbol_table, separator) =
+            self.token_symbol_table(prepared, &pattern_matches, &mut warnings);
+        let boundaries = TokenBoundaries::new(
+            &prepared.lexical_grammar,
+            &prepared.tokens,
+            separator.clone(),
+        );
+        let symbol_table = resolve_separated_symbol_table(
+            &prepared.extracted.variables,
+            self.cost_model,
+            &pattern_matches,
+            &self.config,
+            symbol_table,
+            Some(&boundaries),
+        );
+        let prepared = PreparedResolutions {
+            grammar: prepared,
+            symbol_table,
+            separator,
+        };
+        self.prepared_resolutions(pattern_matches, pattern_errors, warnings, prepared)
+    }
+
+    /// Resolves the grammar like `resolve`, with a future for every group of variables that refer
+    /// to each other instead of a single worklist.
+    pub async fn resolve_async(&self) -> Resolutions<'_> {
+        let (pattern_matches, pattern_errors) = self.pattern_matches();
+        let Some(prepared) = &self.prepared else {
+            let symbol_resolutions = resolve_grammar_async(
+                &self.grammar,
+                self.cost_model,
+                &pattern_matches,
+                &self.config,
+            )
+            .await;
+            return self.resolutions(pattern_matches, pattern_errors, symbol_resolutions);
+        };
+        let mut warnings = vec![];
+        let (symbol_table, separator) =
+            self.token_symbol_table(prepared, &pattern_matches, &mut warnings);
+        let boundaries = TokenBoundaries::new(
+            &prepared.lexical_grammar,
+            &prepared.tokens,
+            separator.clone(),
+        );
+        let symbol_table = resolve_separated_symbol_table_async(
+            &prepared.extracted.variables,
+            self.cost_model,
+            &pattern_matches,
+            &self.config,
+            symbol_table,
+            &boundaries,
+        )
+        .await;
+        let prepared = PreparedResolutions {
+            grammar: prepared,
+            symbol_table,
+            separator,
+        };
+        self.prepared_resolutions(pattern_matches, pattern_errors, warnings, prepared)
+    }
+
+    /// A symbol table with the terminals and externals of the prepared grammar resolved, along
+    /// with the separator to put between tokens that would merge.
+    fn token_symbol_table(
+        &self,
+        prepared: &PreparedGrammar,
+        pattern_matches: &HashMap<String, String>,
+        warnings: &mut Vec<Error>,
+    ) -> (SymbolTable, Option<String>) {
+        let syntax_grammar = &prepared.extracted;
+        let word_token = syntax_grammar.word_token.map(|symbol| symbol.index);
+        // Variables whose rule is a single token became terminals, and can still be pinned.
+        let terminals: Vec<SymbolEntry> = prepared
+            .lexical_grammar
+            .variables
+            .iter()
+            .zip(&prepared.tokens.variables)
+            .enumerate()
+            .map(|(i, (variable, token))| {
+                let resolution = match self.config.variables.get(&variable.name) {
+                    Some(example) if is_variable(variable.kind) => {
+                        Some(Resolution::text(example, self.cost_model))
+                    }
+                    _ if Some(i) == word_token => {
+                        self.resolve_word(prepared, pattern_matches, &token.rule)
+                    }
+                    _ => resolve_token(self.cost_model, pattern_matches, &token.rule),
+                };
+                let resolution = resolution.map(|resolution| resolution.with_token(i));
+                SymbolEntry::new(&variable.name, variable.kind, resolution.map(Arc::new))
+            })
+            .collect();
+        let externals = syntax_grammar
+            .external_tokens
+            .iter()
+            .map(|external_token| {
+                let resolution = self
+                    .config
+                    .externals
+                    .get(&external_token.name)
+                    .map(|example| Arc::new(Resolution::text(example, self.cost_model)));
+                SymbolEntry::new(&external_token.name, external_token.kind, resolution)
+            })
+            .collect();
+        let separator = self.separator(prepared, pattern_matches, &terminals);
+        if separator.is_none() {
+            warnings.push(anyhow!(
+                "None of the grammar's extras can separate tokens, so examples may put tokens \
+                 next to each other that would be lexed as one"
+            ));
+        }
+        let symbol_table = SymbolTable {
+            terminals,
+            externals,
+            ..SymbolTable::default()
+        };
+        (symbol_table, separator)
+    }
+
+    fn prepared_resolutions<'a>(
+        &'a self,
+        pattern_matches: HashMap<String, String>,
+        pattern_errors: Vec<Error>,
+        warnings: Vec<Error>,
+        prepared: PreparedResolutions<'a>,
+    ) -> Resolutions<'a> {
+        let symbol_resolutions = self.symbol_resolutions(&prepared.symbol_table);
+        Resolutions {
+            warnings,
+            prepared: Some(prepared),
+            ..self.resolutions(pattern_matches, pattern_errors, symbol_resolutions)
+        }
+    }
+
+    /// The resolutions of the variables in a resolved symbol table, by name.
+    fn symbol_resolutions(&self, symbol_table: &SymbolTable) -> SymbolResolutions {
+        let mut symbol_resolutions = self.config.symbol_resolutions(self.cost_model);
+        let variables = symbol_table
+            .terminals
+            .iter()
+            .filter(|entry| is_variable(entry.kind));
+        for entry in symbol_table.non_terminals.iter().chain(variables) {
+            if let Some(resolution) = &entry.resolution {
+                symbol_resolutions.insert(entry.name.clone(), resolution.clone());
+            }
+        }
+        symbol_resolutions
+    }
+
+    /// The shortest example of an extra, such as a space for `/\s/`, to put between tokens that
+    /// would merge otherwise. Extras that are neither tokens nor separators, like rules made of
+    /// several tokens, are left out.
+    fn separator(
+        &self,
+        prepared: &PreparedGrammar,
+        pattern_matches: &HashMap<String, String>,
+        terminals: &[SymbolEntry],
+    ) -> Option<String> {
+        let extra_tokens: Vec<usize> = prepared
+            .extracted
+            .extra_symbols
+            .iter()
+            .filter(|symbol| symbol.is_terminal())
+            .map(|symbol| symbol.index)
+            .collect();
+        let separators = prepared.tokens.separators.iter().filter_map(|rule| {
+            let resolution = resolve_token(self.cost_model, pattern_matches, rule)?;
+            Some((rule, resolution.derivation.to_string()))
+        });
+        let tokens = extra_tokens.iter().filter_map(|i| {
+            let resolution = terminals[*i].resolution.as_ref()?;
+            Some((
+                &prepared.tokens.variables[*i].rule,
+                resolution.derivation.to_string(),
+            ))
+        });
+        shortest_separator(
+            &prepared.lexical_grammar,
+            &extra_tokens,
+            separators.chain(tokens).collect(),
+        )
+    }
+
+    /// Resolves the word token like any other token, unless its example would be lexed as a
+    /// keyword instead. It then takes the shortest word that is not a keyword.
+    fn resolve_word(
+        &self,
+        prepared: &PreparedGrammar,
+        pattern_matches: &HashMap<String, String>,
+        rule: &Rule,
+    ) -> Option<Resolution> {
+        let resolution = resolve_token(self.cost_model, pattern_matches, rule);
+        let is_keyword = resolution.as_ref().is_some_and(|resolution| {
+            let text = resolution.derivation.to_string();
+            self.keywords().contains(&text.as_str())
+        });
+        if !is_keyword {
+            return resolution;
+        }
+        let word_token = prepared.extracted.word_token?.index;
+        let sample = sample_word(&prepared.lexical_grammar, word_token, &prepared.keywords)?;
+        Some(Resolution::text(&sample, self.cost_model))
+    }
+
+    fn pattern_matches(&self) -> (HashMap<String, String>, Vec<Error>) {
+        let mut errors = vec![];
+        let pattern_matches =
+            pattern_samples(&self.grammar, self.config.patterns.clone(), &mut errors);
+        (pattern_matches, errors)
+    }
+
+    fn resolutions(
+        &self,
+        pattern_matches: HashMap<String, String>,
+        pattern_errors: Vec<Error>,
+        symbol_resolutions: SymbolResolutions,
+    ) -> Resolutions<'_> {
+        Resolutions {
+            grammar: &self.grammar,
+            interned: self.interned.as_ref(),
+            config: &self.config,
+            cost_model: self.cost_model,
+            pattern_matches,
+            pattern_errors,
+            warnings: self
+                .preparation_error
+                .iter()
+                .map(|error| {
+                    anyhow!(
+                        "Resolving by name, without separating tokens, repeating content or \
+                         avoiding keywords, as tree-sitter would reject the grammar: {error:#}"
+                    )
+                })
+                .collect(),
+            symbol_resolutions,
+            prepared: None,
+        }
+    }
+}
+
+impl PreparedGrammar {
+    fn new(interned: InternedGrammar) -> Result<Self> {
+        let (extracted, tokens) = extract_tokens(interned)?;
+        let lexical_grammar = expand_tokens(&tokens)?;
+        let syntax_grammar = flatten_grammar(expand_repeats(extracted.clone()))?;
+        let inlines = process_inlines(&syntax_grammar, &lexical_grammar)?;
+        let keywords = extracted
+            .word_token
+            .map(|word_token| extract_keywords(&lexical_grammar, &tokens, word_token.index))
+            .unwrap_or_default();
+        let mut extracted = extracted;
+        extracted.hide_inlined_variables();
+        Ok(PreparedGrammar {
+            extracted,
+            tokens,
+            lexical_grammar,
+            syntax_grammar,
+            inlines,
+            keywords,
+        })
+    }
+}
+
+impl<'a> Resolutions<'a> {
+    /// The outcome for every variable, in grammar order.
+    pub fn examples(&self) -> impl Iterator<Item = VariableExample<'_>> {
+        self.grammar
+            .variables
+            .iter()
+            .enumerate()
+            .map(|(index, variable)| {
+                let resolution = self.get(&variable.name);
+                let kind = self.kind(index);
+                let status = if resolution.is_none() {
+                    Status::Unresolved
+                } else if self.config.is_pinned(&variable.name) {
+                    Status::Pinned
+                } else {
+                    Status::Resolved
+                };
+                VariableExample {
+                    name: &variable.name,
+                    kind,
+                    status,
+                    resolution,
+                }
+            })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Resolution> {
+        self.symbol_resolutions.get(name).map(AsRef::as_ref)
+    }
+
+    /// The visible variables without an example, in grammar order.
+    pub fn unresolved_visible(&self) -> Vec<&'a str> {
+        self.grammar
+            .variables
+            .iter()
+            .enumerate()
+            .filter(|(index, variable)| {
+                self.kind(*index).is_visible()
+                    && !self.symbol_resolutions.contains_key(&variable.name)
+            })
+            .map(|(_, variable)| variable.name.as_str())
+            .collect()
+    }
+
+    /// The kind of node a variable stands for. Inlined variables and supertypes are hidden as
+    /// well, which only the interned grammar knows; without one, only the name tells.
+    fn kind(&self, index: usize) -> VariableType {
+        match self.interned {
+            Some(interned) => interned.variables[index].kind,
+            None if is_visible(&self.grammar.variables[index].name) => {
+                self.grammar.variables[index].kind
+            }
+            None => VariableType::Hidden,
+        }
+    }
+
+    /// Explains why variables could not be resolved, going by the grammar they were resolved from.
+    pub fn diagnose(&self) -> Report {
+        let Some(prepared) = &self.prepared else {
+            return diagnose(
+                self.grammar,
+                &self.pattern_matches,
+                &self.symbol_resolutions,
+            );
+        };
+        let grammar = prepared.grammar;
+        diagnose_interned(
+            self.grammar,
+            &grammar.extracted.variables,
+            &grammar.tokens,
+            grammar.extracted.word_token.map(|symbol| symbol.index),
+            &self.pattern_matches,
+            &prepared.symbol_table,
+        )
+    }
+
+    /// The minimal example for every alternative of the top-level choice of a variable, such as
+    /// every kind of statement for `statement`. Alternatives that are hidden variables or
+    /// supertypes are expanded into the visible kinds of nodes they stand for. `None` if there is
+    /// no such variable, or the grammar was resolved by name because tree-sitter would reject it.
+    pub fn alternatives(&self, name: &str) -> Option<Vec<Alternative>> {
+        let prepared = self.prepared.as_ref()?;
+        let grammar = &prepared.grammar.extracted;
+        let Some(index) = grammar
+            .variables
+            .iter()
+            .position(|variable| variable.name == name)
+        else {
+            // Variables whose rule is a single token became terminals, without a choice.
+            let entry = prepared
+                .symbol_table
+                .terminals
+                .iter()
+                .find(|entry| entry.name == name && is_variable(entry.kind))?;
+            return Some(vec![Alternative {
+                tag: entry.is_visible().then(|| AlternativeTag::symbol(entry)),
+                resolution: entry.resolution.clone(),
+            }]);
+        };
+
+        let boundaries = TokenBoundaries::new(
+            &prepared.grammar.lexical_grammar,
+            &prepared.grammar.tokens,
+            prepared.separator.clone(),
+        );
+        Some(alternatives(
+            grammar,
+            &prepared.symbol_table,
+            index,
+            |variable, rule| {
+                resolve_variable_rule(
+                    self.cost_model,
+                    &self.pattern_matches,
+                    self.config,
+                    &prepared.symbol_table,
+                    Some(&boundaries),
+                    variable,
+                    rule,
+                )
+            },
+        ))
+    }
+
+    /// The derivations of a variable in order of cost, cheapest first, each with a text of its
+    /// own. Other than its example, these show what else the variable stands for, like the ways to
+    /// fill in an optional part or to continue a list. `None` if there is no such variable.
+    pub fn sentences(&self, name: &str, bound: SentenceBound) -> Option<Sentences<'_>> {
+        let context = |variables, symbols| Context {
+            variables,
+            symbols,
+            cost_model: self.cost_model,
+            pattern_matches: &self.pattern_matches,
+            config: self.config,
+        };
+        let Some(prepared) = &self.prepared else {
+            let variables = &self.grammar.variables;
+            let index = variables
+                .iter()
+                .position(|variable| variable.name == name)?;
+            let symbols = Symbols::Named(&self.symbol_resolutions);
+            return Some(Sentences::new(context(variables, symbols), index, bound));
+        };
+
+        let symbols = Symbols::Interned {
+            symbol_table: &prepared.symbol_table,
+            boundaries: Box::new(TokenBoundaries::new(
+                &prepared.grammar.lexical_grammar,
+                &prepared.grammar.tokens,
+                prepared.separator.clone(),
+            )),
+        };
+        let variables = &prepared.grammar.extracted.variables;
+        match variables.iter().position(|variable| variable.name == name) {
+            Some(index) => Some(Sentences::new(context(variables, symbols), index, bound)),
+            // Variables whose rule is a single token became terminals, with a single example.
+            None => {
+                let entry = prepared
+                    .symbol_table
+                    .terminals
+                    .iter()
+                    .find(|entry| entry.name == name && is_variable(entry.kind))?;
+                let example = entry.resolution.as_deref().cloned();
+                Some(Sentences::single(context(variables, symbols), example))
+            }
+        }
+    }
+
+    /// Patterns that failed to compile or accept no string at all, and so have no sample.
+    pub fn pattern_errors(&self) -> &[Error] {
+        &self.pattern_errors
+    }
+
+    /// Problems with the grammar that don't stop variables from resolving, but may make their
+    /// examples invalid.
+    pub fn warnings(&self) -> &[Error] {
+        &self.warnings
+    }
+
+    pub fn grammar(&self) -> &'a InputGrammar {
+        self.grammar
+    }
+}
+
+impl VariableExample<'_> {
+    pub fn text(&self) -> Option<String> {
+        self.resolution
+            .map(|resolution| resolution.derivation.to_string())
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.kind.is_visible()
+    }
+}
+
+/// Maps every variable name to its example, in grammar order.
+impl Serialize for Resolutions<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.grammar.variables.len()))?;
+        for example in self.examples() {
+            map.serialize_entry(example.name, &example)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for VariableExample<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut example = serializer.serialize_struct("VariableExample", 4)?;
+        example.serialize_field("example", &self.text())?;
+        example.serialize_field("cost", &self.resolution.map(|resolution| resolution.cost))?;
+        example.serialize_field("kind", &self.kind)?;
+        example.serialize_field("status", &self.status)?;
+        example.end()
+    }
+}
+
+/// Whether a terminal stands for a whole grammar variable, rather than for a string or a token
+/// extracted out of a variable's rule.
+fn is_variable(kind: VariableType) -> bool {
+    kind == VariableType::Named || kind == VariableType::Hidden
+}
+
+/// Whether a variable shows up as a node in the syntax tree.
+fn is_visible(name: &str) -> bool {
+    !name.starts_with('_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::RepeatCount, tree_sitter_cli::parse_grammar::parse_grammar};
+
+    #[test]
+    fn test_resolver() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "list",
+            "rules": {
+                "list": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "STRING", "value": "[" },
+                        { "type": "SYMBOL", "name": "_item" },
+                        { "type": "STRING", "value": "]" }
+                    ]
+                },
+                "_item": { "type": "PATTERN", "value": "[0-9]" },
+                "stuck": { "type": "SYMBOL", "name": "stuck" }
+            }
+        }"#,
+        )
+        .unwrap();
+        let resolver = Resolver::new(grammar);
+
+        let resolutions = resolver.resolve();
+        let examples: Vec<(&str, Option<String>, bool)> = resolutions
+            .examples()
+            .map(|example| (example.name, example.text(), example.is_visible()))
+            .collect();
+        assert_eq!(
+            examples,
+            vec![
+                ("list", Some("[0]".to_string()), true),
+                ("_item", Some("0".to_string()), false),
+                ("stuck", None, true),
+            ]
+        );
+        assert_eq!(resolutions.unresolved_visible(), vec!["stuck"]);
+        assert_eq!(
+            resolutions.diagnose().cycles,
+            vec![vec!["stuck".to_string()]]
+        );
+        assert!(resolutions.pattern_errors().is_empty());
+    }
+
+    #[test]
+    fn test_serialize_resolutions() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "order",
+            "rules": {
+                "zeta": { "type": "SYMBOL", "name": "_alpha" },
+                "_alpha": { "type": "STRING", "value": "a" },
+                "mu": { "type": "PATTERN", "value": "[0-9]+" },
+                "beta": { "type": "SYMBOL", "name": "beta" }
+            }
+        }"#,
+        )
+        .unwrap();
+        let resolver = Resolver::new(grammar).with_config(Config {
+            variables: HashMap::from([("mu".to_string(), "42".to_string())]),
+            ..Config::default()
+        });
+
+        assert_eq!(
+            serde_json::to_string(&resolver.resolve()).unwrap(),
+            concat!(
+                r#"{"zeta":{"example":"a","cost":1,"kind":"named","status":"resolved"},"#,
+                r#""_alpha":{"example":"a","cost":1,"kind":"hidden","status":"resolved"},"#,
+                r#""mu":{"example":"42","cost":2,"kind":"named","status":"pinned"},"#,
+                r#""beta":{"example":null,"cost":null,"kind":"named","status":"unresolved"}}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_syntax_grammar() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "list",
+            "rules": {
+                "list": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "STRING", "value": "[" },
+                        {
+                            "type": "REPEAT",
+                            "content": {
+                                "type": "FIELD",
+                                "name": "item",
+                                "content": { "type": "SYMBOL", "name": "number" }
+                            }
+                        },
+                        { "type": "STRING", "value": "]" }
+                    ]
+                },
+                "number": { "type": "PATTERN", "value": "[0-9]+" }
+            }
+        }"#,
+        )
+        .unwrap();
+        let resolver = Resolver::new(grammar);
+        let syntax_grammar = resolver.syntax_grammar().unwrap();
+
+        let productions: Vec<(&str, Vec<String>)> = syntax_grammar
+            .variables
+            .iter()
+            .map(|variable| {
+                let productions = variable
+                    .productions
+                    .iter()
+                    .map(|production| {
+                        let steps: Vec<String> = production
+                            .steps
+                            .iter()
+                            .map(|step| {
+                                let name = resolver.symbol_name(step.symbol).unwrap();
+                                match &step.field_name {
+                                    Some(field_name) => format!("{field_name}: {name}"),
+                                    None => name.to_string(),
+                                }
+                            })
+                            .collect();
+                        steps.join(" ")
+                    })
+                    .collect();
+                (variable.name.as_str(), productions)
+            })
+            .collect();
+        assert_eq!(
+            productions,
+            vec![
+                (
+                    "list",
+                    vec!["[ list_repeat1 ]".to_string(), "[ ]".to_string()]
+                ),
+                (
+                    "list_repeat1",
+                    vec![
+                        "list_repeat1 list_repeat1".to_string(),
+                        "item: number".to_string()
+                    ]
+                ),
+            ]
+        );
+        assert_eq!(
+            resolver
+                .resolve()
+                .get("list")
+                .unwrap()
+                .derivation
+                .to_string(),
+            "[]"
+        );
+    }
+
+    #[test]
+    fn test_inlined_variables() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "calls",
+            "inline": ["arguments"],
+            "rules": {
+                "call": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "SYMBOL", "name": "name" },
+                        { "type": "SYMBOL", "name": "arguments" }
+                    ]
+                },
+                "arguments": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "STRING", "value": "(" },
+                        { "type": "STRING", "value": ")" }
+                    ]
+                },
+                "name": { "type": "PATTERN", "value": "[a-z]" }
+            }
+        }"#,
+        )
+        .unwrap();
+        let resolver = Resolver::new(grammar).with_cost_model(CostModel::Nodes);
+
+        let syntax_grammar = resolver.syntax_grammar().unwrap();
+        let call = &syntax_grammar.variables[0].productions[0];
+        let inlined: Vec<Vec<&str>> = resolver
+            .inlined_productions(call, 1)
+            .unwrap()
+            .map(|production| {
+                production
+                    .steps
+                    .iter()
+                    .map(|step| resolver.symbol_name(step.symbol).unwrap())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(inlined, vec![vec!["name", "(", ")"]]);
+        assert!(resolver.inlined_productions(call, 0).is_none());
+
+        // `arguments` doesn't add a node of its own to the `name` token and its node.
+        let resolutions = resolver.resolve();
+        let call = resolutions.examples().next().unwrap();
+        assert_eq!(call.text(), Some("a()".to_string()));
+        assert_eq!(call.resolution.unwrap().metrics.nodes, 4);
+        let arguments = resolutions.examples().nth(1).unwrap();
+        assert_eq!(arguments.kind, VariableType::Hidden);
+    }
+
+    #[test]
+    fn test_unresolved_inlined_variables_are_hidden() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "loops",
+            "inline": ["open"],
+            "rules": {
+                "program": {
+                    "type": "CHOICE",
+                    "members": [
+                        { "type": "STRING", "value": "x" },
+                        { "type": "SYMBOL", "name": "open" }
+                    ]
+                },
+                "open": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "STRING", "value": "(" },
+                        { "type": "SYMBOL", "name": "close" }
+                    ]
+                },
+                "close": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "SYMBOL", "name": "open" },
+                        { "type": "STRING", "value": ")" }
+                    ]
+                }
+            }
+        }"#,
+        )
+        .unwrap();
+        let resolver = Resolver::new(grammar);
+        assert!(resolver.preparation_error().is_none());
+
+        let resolutions = resolver.resolve();
+        assert_eq!(resolutions.unresolved_visible(), vec!["close"]);
+        let open = resolutions.examples().nth(1).unwrap();
+        assert!(!open.is_visible());
+    }
+
+    #[test]
+    #[ntest::timeout(1000)]
+    fn test_inlined_self_reference() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "statements",
+            "inline": ["statement"],
+            "rules": {
+                "program": { "type": "SYMBOL", "name": "statement" },
+                "statement": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "STRING", "value": "s" },
+                        { "type": "SYMBOL", "name": "statement" }
+                    ]
+                }
+            }
+        }"#,
+        )
+        .unwrap();
+        let resolver = Resolver::new(grammar);
+        assert_eq!(
+            resolver.preparation_error().unwrap().to_string(),
+            "Rule `statement` cannot be inlined because it contains a reference to itself."
+        );
+
+        // Resolved by name, where `statement` is still hidden.
+        let resolutions = resolver.resolve();
+        assert_eq!(resolutions.unresolved_visible(), vec!["program"]);
+    }
+
+    #[test]
+    fn test_word_examples_avoid_keywords() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "my_lang",
+            "word": "identifier",
+            "rules": {
+                "statement": {
+                    "type": "CHOICE",
+                    "members": [
+                        {
+                            "type": "SEQ",
+                            "members": [
+                                { "type": "STRING", "value": "if" },
+                                { "type": "SYMBOL", "name": "identifier" }
+                            ]
+                        },
+                        {
+                            "type": "SEQ",
+                            "members": [
+                                { "type": "STRING", "value": "a" },
+                                { "type": "STRING", "value": "+" }
+                            ]
+                        }
+                    ]
+                },
+                "identifier": { "type": "PATTERN", "value": "[a-z]+" }
+            }
+        }"#,
+        )
+        .unwrap();
+        let resolver = Resolver::new(grammar);
+        assert_eq!(resolver.keywords(), vec!["if", "a"]);
+
+        let resolutions = resolver.resolve();
+        let identifier = resolutions.get("identifier").unwrap();
+        assert_eq!(identifier.derivation.to_string(), "b");
+    }
+
+    #[test]
+    fn test_diagnose_word_token_of_keywords() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "my_lang",
+            "word": "identifier",
+            "rules": {
+                "program": {
+                    "type": "REPEAT",
+                    "content": {
+                        "type": "CHOICE",
+                        "members": [
+                            { "type": "SYMBOL", "name": "keyword" },
+                            { "type": "SYMBOL", "name": "assignment" }
+                        ]
+                    }
+                },
+                "keyword": {
+                    "type": "CHOICE",
+                    "members": [
+                        { "type": "STRING", "value": "a" },
+                        { "type": "STRING", "value": "b" }
+                    ]
+                },
+                "assignment": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "SYMBOL", "name": "identifier" },
+                        { "type": "STRING", "value": "=" }
+                    ]
+                },
+                "identifier": { "type": "PATTERN", "value": "[ab]" }
+            }
+        }"#,
+        )
+        .unwrap();
+        let resolver = Resolver::new(grammar);
+        let resolutions = resolver.resolve();
+        assert_eq!(
+            resolutions.unresolved_visible(),
+            vec!["assignment", "identifier"]
+        );
+
+        let report = resolutions.diagnose();
+        let diagnoses: Vec<(&str, Vec<String>, Vec<String>)> = report
+            .unresolved
+            .iter()
+            .map(|diagnosis| {
+                let blockers = diagnosis
+                    .blockers
+                    .iter()
+                    .map(|blocked_rule| blocked_rule.blocker.to_string())
+                    .collect();
+                (diagnosis.name.as_str(), blockers, diagnosis.chain.clone())
+            })
+            .collect();
+        assert_eq!(
+            diagnoses,
+            vec![
+                (
+                    "assignment",
+                    vec!["unresolved `identifier`".to_string()],
+                    vec!["assignment".to_string(), "identifier".to_string()]
+                ),
+                (
+                    "identifier",
+                    vec!["word token `identifier` only matches keywords".to_string()],
+                    vec!["identifier".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_adjacent_tokens_are_separated() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "my_lang",
+            "extras": [{ "type": "PATTERN", "value": "\\s+" }],
+            "rules": {
+                "program": {
+                    "type": "CHOICE",
+                    "members": [
+                        { "type": "SYMBOL", "name": "declaration" },
+                        { "type": "SYMBOL", "name": "sum" },
+                        { "type": "SYMBOL", "name": "member" },
+                        { "type": "SYMBOL", "name": "attached" }
+                    ]
+                },
+                "declaration": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "STRING", "value": "let" },
+                        { "type": "SYMBOL", "name": "identifier" },
+                        { "type": "STRING", "value": ";" }
+                    ]
+                },
+                "sum": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "SYMBOL", "name": "number" },
+                        { "type": "STRING", "value": "+" },
+                        { "type": "SYMBOL", "name": "number" }
+                    ]
+                },
+                "member": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "SYMBOL", "name": "number" },
+                        { "type": "STRING", "value": "." },
+                        { "type": "SYMBOL", "name": "identifier" }
+                    ]
+                },
+                "attached": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "STRING", "value": "let" },
+                        {
+                            "type": "IMMEDIATE_TOKEN",
+                            "content": { "type": "STRING", "value": "x" }
+                        }
+                    ]
+                },
+                "identifier": { "type": "PATTERN", "value": "[a-z]+" },
+                "number": { "type": "PATTERN", "value": "\\d+(\\.\\d*)?" }
+            }
+        }"#,
+        )
+        .unwrap();
+
+        let resolver = Resolver::new(grammar);
+        let resolutions = resolver.resolve();
+        let example = |name: &str| resolutions.get(name).unwrap().derivation.to_string();
+        assert_eq!(example("declaration"), "let a;");
+        assert_eq!(example("sum"), "0+0");
+        // `0.` would be a single number.
+        assert_eq!(example("member"), "0 .a");
+        // Immediate tokens have to follow the previous token directly.
+        assert_eq!(example("attached"), "letx");
+    }
+
+    #[test]
+    fn test_separator_from_extras() {
+        let grammar = |extras: &str| {
+            parse_grammar(&format!(
+                r##"{{
+                "name": "my_lang",
+                "extras": {extras},
+                "rules": {{
+                    "declaration": {{
+                        "type": "SEQ",
+                        "members": [
+                            {{ "type": "STRING", "value": "let" }},
+                            {{ "type": "SYMBOL", "name": "identifier" }}
+                        ]
+                    }},
+                    "identifier": {{ "type": "PATTERN", "value": "[a-z]+" }},
+                    "line_comment": {{ "type": "PATTERN", "value": "#.*" }},
+                    "block_comment": {{ "type": "PATTERN", "value": "<[^>]*>" }}
+                }}
+            }}"##
+            ))
+            .unwrap()
+        };
+        let resolve = |extras: &str| {
+            let resolver = Resolver::new(grammar(extras));
+            let resolutions = resolver.resolve();
+            let example = resolutions
+                .get("declaration")
+                .unwrap()
+                .derivation
+                .to_string();
+            (example, resolutions.warnings().len())
+        };
+
+        let comment = r#"{ "type": "SYMBOL", "name": "line_comment" }"#;
+        let newline = r#"{ "type": "PATTERN", "value": "\\r?\\n" }"#;
+        let block_comment = r#"{ "type": "SYMBOL", "name": "block_comment" }"#;
+        let space = r#"{ "type": "PATTERN", "value": "[ \\n]" }"#;
+        // A line comment would swallow the identifier.
+        assert_eq!(
+            resolve(&format!("[{comment}, {newline}]")),
+            ("let\na".to_string(), 0)
+        );
+        assert_eq!(
+            resolve(&format!("[{comment}, {block_comment}]")),
+            ("let<>a".to_string(), 0)
+        );
+        assert_eq!(resolve(&format!("[{space}]")), ("let a".to_string(), 0));
+        assert_eq!(resolve(&format!("[{comment}]")), ("leta".to_string(), 1));
+    }
+
+    #[tokio::test]
+    async fn test_repeat_copies_are_separated() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "my_lang",
+            "extras": [{ "type": "PATTERN", "value": "\\s" }],
+            "rules": {
+                "names": {
+                    "type": "REPEAT",
+                    "content": { "type": "SYMBOL", "name": "identifier" }
+                },
+                "identifier": { "type": "PATTERN", "value": "[a-z]+" },
+                "digits": {
+                    "type": "REPEAT1",
+                    "content": { "type": "SYMBOL", "name": "number" }
+                },
+                "number": { "type": "PATTERN", "value": "\\d+" }
+            }
+        }"#,
+        )
+        .unwrap();
+        let config = Config {
+            repeat: RepeatCount::Fixed(2),
+            patterns: HashMap::from([("\\d+".to_string(), "1".to_string())]),
+            ..Config::default()
+        };
+        let resolver = Resolver::new(grammar).with_config(config);
+        for resolutions in [resolver.resolve(), resolver.resolve_async().await] {
+            let examples = ["names", "digits"]
+                .map(|name| resolutions.get(name).unwrap().derivation.to_string());
+            assert_eq!(examples, ["a a", "1 1"]);
+        }
+    }
+}
@@ -0,0 +1,755 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, BinaryHeap, HashMap, HashSet},
+    iter::Sum,
+    ops::Add,
+    sync::Arc,
+};
+
+use crate::{
+    config::Config,
+    cost::{CostModel, Metrics},
+    derivation::{Derivation, TokenEdge},
+    resolve::{
+        concat, metadata_metrics, resolve_in_variable, reverse_dependencies, symbol_metrics,
+        Resolution, SymbolEntry, SymbolLookup, SymbolResolutions, SymbolTable,
+    },
+    token_boundaries::TokenBoundaries,
+    tree_sitter_cli::{
+        grammars::{Variable, VariableType},
+        rules::{MetadataParams, Rule, Symbol},
+    },
+};
+
+/// Limits on the derivations that `Sentences` goes through. Without any, it goes on for as long
+/// as the grammar allows, which for a recursive grammar is forever.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SentenceBound {
+    /// How many levels of variables below the enumerated one are expanded into their
+    /// alternatives. Variables nested deeper keep their cheapest example. Unlike a bound on the
+    /// length, this also ends the search in grammars with cycles that add nothing to the cost, like
+    /// a variable that derives itself through hidden variables.
+    pub max_depth: Option<usize>,
+    /// The longest text of a derivation, in characters.
+    pub max_length: Option<usize>,
+}
+
+/// The derivations of a variable, cheapest first, each with a text of its own. Choices take every
+/// alternative and repetitions any number of copies, while tokens and pinned variables keep the
+/// one example they resolve to.
+///
+/// Derivations are searched best-first, from the leftmost part that is not derived yet. A partial
+/// derivation is queued by a lower bound on the cost of the derivations it can be finished to, so
+/// that a finished derivation is only taken from the queue once nothing cheaper can come after.
+#[derive(Debug)]
+pub struct Sentences<'a> {
+    grammar: Grammar<'a>,
+    bound: SentenceBound,
+    queue: BinaryHeap<Queued<'a>>,
+    /// Number of partial derivations queued so far, which breaks ties in favor of earlier ones.
+    queued: usize,
+    /// The texts of the derivations yielded so far.
+    yielded: HashSet<String>,
+}
+
+/// What derivations are built from.
+#[derive(Debug)]
+pub(crate) struct Context<'a> {
+    pub(crate) variables: &'a [Variable],
+    pub(crate) symbols: Symbols<'a>,
+    pub(crate) cost_model: CostModel,
+    pub(crate) pattern_matches: &'a HashMap<String, String>,
+    pub(crate) config: &'a Config,
+}
+
+/// The examples for the symbols that are not expanded, either by name for an input grammar or in
+/// the symbol table of a prepared one, whose adjacent tokens are separated where they would merge.
+#[derive(Debug)]
+pub(crate) enum Symbols<'a> {
+    Named(&'a SymbolResolutions),
+    Interned {
+        symbol_table: &'a SymbolTable,
+        boundaries: Box<TokenBoundaries<'a>>,
+    },
+}
+
+#[derive(Debug)]
+struct Grammar<'a> {
+    context: Context<'a>,
+    /// The indices of the variables by name, for rules that refer to them by name.
+    indices: HashMap<&'a str, usize>,
+    /// Lower bounds on the examples of every variable, `None` for those that don't resolve.
+    minimums: Vec<Option<Minimum>>,
+}
+
+/// Lower bounds on the cost and the length of an example, which may come from different examples.
+/// Separators are left out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Minimum {
+    cost: usize,
+    length: usize,
+}
+
+/// A derivation that is not finished yet.
+#[derive(Clone, Debug)]
+struct State<'a> {
+    /// What is left to derive, the next item last.
+    pending: Vec<Item<'a>>,
+    /// The resolutions derived so far, with `None` where a node starts that is not closed yet.
+    built: Vec<Option<Resolution>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Item<'a> {
+    Rule(Part<'a>),
+    /// Any number of further copies of a repetition's content.
+    Copies(Part<'a>),
+    /// Drops the derivation if the copy of a repetition's content derived last is empty, as the
+    /// same text comes without that copy.
+    NonEmpty,
+    /// Closes the node that started last.
+    Close(Node<'a>),
+}
+
+/// A rule that is part of the rule of the variable at `variable`, which is nested `depth`
+/// variables deep.
+#[derive(Clone, Copy, Debug)]
+struct Part<'a> {
+    rule: &'a Rule,
+    variable: usize,
+    depth: usize,
+    minimum: Minimum,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Node<'a> {
+    Seq,
+    Metadata(&'a MetadataParams),
+    Symbol { name: &'a str, is_visible: bool },
+}
+
+#[derive(Debug)]
+struct Queued<'a> {
+    cost: usize,
+    serial: usize,
+    state: State<'a>,
+}
+
+impl<'a> Sentences<'a> {
+    /// Goes through the derivations of the variable at `index`.
+    pub(crate) fn new(context: Context<'a>, index: usize, bound: SentenceBound) -> Self {
+        let mut sentences = Sentences::start(context, bound);
+        let grammar = &sentences.grammar;
+        let variables = grammar.context.variables;
+        let variable = &variables[index];
+        let start = if grammar.context.config.is_pinned(&variable.name) {
+            grammar
+                .context
+                .symbols
+                .example(index, &variable.name)
+                .map(|example| State {
+                    pending: vec![],
+                    built: vec![Some(example.as_ref().clone())],
+                })
+        } else {
+            grammar.part(&variable.rule, index, 0).map(|part| State {
+                pending: vec![Item::Rule(part)],
+                built: vec![],
+            })
+        };
+        sentences.push(start);
+        sentences
+    }
+
+    /// Yields `example` alone, for variables that became tokens and have no rule to expand.
+    pub(crate) fn single(context: Context<'a>, example: Option<Resolution>) -> Self {
+        let mut sentences = Sentences::start(context, SentenceBound::default());
+        sentences.push(example.map(|example| State {
+            pending: vec![],
+            built: vec![Some(example)],
+        }));
+        sentences
+    }
+
+    fn start(context: Context<'a>, bound: SentenceBound) -> Self {
+        let indices = context
+            .variables
+            .iter()
+            .enumerate()
+            .map(|(index, variable)| (variable.name.as_str(), index))
+            .collect();
+        let mut grammar = Grammar {
+            minimums: vec![None; context.variables.len()],
+            context,
+            indices,
+        };
+        grammar.minimize();
+        Sentences {
+            grammar,
+            bound,
+            queue: BinaryHeap::new(),
+            queued: 0,
+            yielded: HashSet::new(),
+        }
+    }
+
+    /// Queues partial derivations, unless they are bound to be too long.
+    fn push(&mut self, states: impl IntoIterator<Item = State<'a>>) {
+        for state in states {
+            let minimum = state.minimum();
+            if self
+                .bound
+                .max_length
+                .is_some_and(|max_length| minimum.length > max_length)
+            {
+                continue;
+            }
+            self.queue.push(Queued {
+                cost: minimum.cost,
+                serial: self.queued,
+                state,
+            });
+            self.queued += 1;
+        }
+    }
+}
+
+impl Iterator for Sentences<'_> {
+    type Item = Resolution;
+
+    fn next(&mut self) -> Option<Resolution> {
+        while let Some(Queued { state, .. }) = self.queue.pop() {
+            if !state.pending.is_empty() {
+                let successors = self.grammar.expand(state, self.bound);
+                self.push(successors);
+                continue;
+            }
+            let Some(Some(resolution)) = state.built.into_iter().next() else {
+                continue;
+            };
+            if self.yielded.insert(resolution.derivation.to_string()) {
+                return Some(resolution);
+            }
+        }
+        None
+    }
+}
+
+impl<'a> Grammar<'a> {
+    /// Computes the lower bounds on the examples of all variables. Like resolving the grammar,
+    /// a variable is only re-evaluated once a variable it references got cheaper.
+    fn minimize(&mut self) {
+        let variables = self.context.variables;
+        let dependents = reverse_dependencies(variables);
+        let mut worklist: BTreeSet<usize> = (0..variables.len()).collect();
+        // Bounds only ever go down, so this terminates.
+        while let Some(index) = worklist.pop_first() {
+            let Some(minimum) = self.minimum(&variables[index].rule, index) else {
+                continue;
+            };
+            let previous = self.minimums[index];
+            let minimum = previous.map_or(minimum, |previous| previous.min(minimum));
+            if Some(minimum) == previous {
+                continue;
+            }
+            self.minimums[index] = Some(minimum);
+            worklist.extend(&dependents[index]);
+        }
+    }
+
+    /// A lower bound on the derivations of `rule`, which is part of the rule of the variable at
+    /// `variable`. `None` if it has none.
+    fn minimum(&self, rule: &Rule, variable: usize) -> Option<Minimum> {
+        match rule {
+            Rule::Choice(rules) => rules
+                .iter()
+                .filter_map(|rule| self.minimum(rule, variable))
+                .reduce(Minimum::min),
+            Rule::Seq(rules) => rules.iter().map(|rule| self.minimum(rule, variable)).sum(),
+            Rule::Repeat(rule) => self.minimum(rule, variable),
+            Rule::Metadata { params, rule } if !params.is_token => self.minimum(rule, variable),
+            _ => match self.non_terminal(rule) {
+                Some((index, node)) => Some(self.minimums[index]? + self.node_minimum(node)),
+                None => self.resolve(rule, variable).as_ref().map(Minimum::of),
+            },
+        }
+    }
+
+    /// What closing `node` adds to the minimum of its content.
+    fn node_minimum(&self, node: Node) -> Minimum {
+        let is_visible = matches!(
+            node,
+            Node::Symbol {
+                is_visible: true,
+                ..
+            }
+        );
+        let metrics = Metrics {
+            nodes: usize::from(is_visible),
+            ..Metrics::default()
+        };
+        Minimum {
+            cost: self.context.cost_model.cost(metrics),
+            length: 0,
+        }
+    }
+
+    fn part(&self, rule: &'a Rule, variable: usize, depth: usize) -> Option<Part<'a>> {
+        Some(Part {
+            rule,
+            variable,
+            depth,
+            minimum: self.minimum(rule, variable)?,
+        })
+    }
+
+    /// The non-terminal that `rule` refers to, if it is one that is expanded rather than pinned,
+    /// along with the node it closes.
+    fn non_terminal(&self, rule: &'a Rule) -> Option<(usize, Node<'a>)> {
+        let (index, node) = match rule {
+            Rule::NamedSymbol(name) => {
+                let node = Node::Symbol {
+                    name,
+                    is_visible: !name.starts_with('_'),
+                };
+                (*self.indices.get(name.as_str())?, node)
+            }
+            Rule::Symbol(symbol) if symbol.is_non_terminal() => {
+                let entry = self.context.symbols.entry(*symbol)?;
+                let node = Node::Symbol {
+                    name: &entry.name,
+                    is_visible: entry.kind == VariableType::Named,
+                };
+                (symbol.index, node)
+            }
+            _ => return None,
+        };
+        let variable = &self.context.variables[index];
+        (!self.context.config.is_pinned(&variable.name)).then_some((index, node))
+    }
+
+    /// Resolves a rule that is not expanded, the way resolving the grammar does.
+    fn resolve(&self, rule: &Rule, variable: usize) -> Option<Resolution> {
+        resolve_in_variable(
+            self.context.cost_model,
+            self.context.pattern_matches,
+            self.context.config,
+            &self.context.symbols,
+            &self.context.variables[variable],
+            rule,
+        )
+    }
+
+    /// The partial derivations that follow from deriving the next item of `state`.
+    fn expand(&self, mut state: State<'a>, bound: SentenceBound) -> Vec<State<'a>> {
+        let Some(item) = state.pending.pop() else {
+            return vec![];
+        };
+        match item {
+            Item::Rule(part) => self.expand_rule(state, part, bound),
+            Item::Copies(part) => {
+                let mut more = state.clone();
+                more.pending
+                    .extend([Item::Copies(part), Item::NonEmpty, Item::Rule(part)]);
+                vec![state, more]
+            }
+            Item::NonEmpty => match state.built.last() {
+                Some(Some(copy)) if copy.metrics.length == 0 => vec![],
+                _ => vec![state],
+            },
+            Item::Close(node) => {
+                self.close(&mut state, node);
+                vec![state]
+            }
+        }
+    }
+
+    fn expand_rule(
+        &self,
+        mut state: State<'a>,
+        part: Part<'a>,
+        bound: SentenceBound,
+    ) -> Vec<State<'a>> {
+        let Part {
+            rule,
+            variable,
+            depth,
+            ..
+        } = part;
+        match rule {
+            Rule::Choice(rules) => rules
+                .iter()
+                .filter_map(|rule| {
+                    let mut state = state.clone();
+                    state
+                        .pending
+                        .push(Item::Rule(self.part(rule, variable, depth)?));
+                    Some(state)
+                })
+                .collect(),
+            Rule::Seq(rules) => {
+                let Some(parts) = rules
+                    .iter()
+                    .rev()
+                    .map(|rule| self.part(rule, variable, depth))
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    return vec![];
+                };
+                state.open(Node::Seq, parts.into_iter().map(Item::Rule));
+                vec![state]
+            }
+            Rule::Repeat(rule) => {
+                let Some(part) = self.part(rule, variable, depth) else {
+                    return vec![];
+                };
+                state.open(Node::Seq, [Item::Copies(part), Item::Rule(part)]);
+                vec![state]
+            }
+            Rule::Metadata { params, rule } if !params.is_token => {
+                let Some(part) = self.part(rule, variable, depth) else {
+                    return vec![];
+                };
+                state.open(Node::Metadata(params), [Item::Rule(part)]);
+                vec![state]
+            }
+            _ => {
+                let expanded = self
+                    .non_terminal(rule)
+                    .filter(|_| bound.max_depth.is_none_or(|max_depth| depth < max_depth));
+                if let Some((index, node)) = expanded {
+                    let rule = &self.context.variables[index].rule;
+                    let Some(part) = self.part(rule, index, depth + 1) else {
+                        return vec![];
+                    };
+                    state.open(node, [Item::Rule(part)]);
+                    return vec![state];
+                }
+                let Some(resolution) = self.resolve(rule, variable) else {
+                    return vec![];
+                };
+                state.built.push(Some(resolution));
+                vec![state]
+            }
+        }
+    }
+
+    /// Replaces the resolutions of the node that started last by the node's own resolution.
+    fn close(&self, state: &mut State<'a>, node: Node<'a>) {
+        let cost_model = self.context.cost_model;
+        let start = state.built.iter().rposition(Option::is_none).unwrap_or(0);
+        let mut content: Vec<Resolution> = state.built.drain(start..).flatten().collect();
+        let resolution = match node {
+            Node::Seq => concat(cost_model, &self.context.symbols, content),
+            Node::Metadata(params) => {
+                let content = content.pop().expect("metadata wraps a single rule");
+                Resolution::new(
+                    cost_model,
+                    Derivation::Metadata {
+                        params: params.clone(),
+                        derivation: Box::new(content.derivation),
+                    },
+                    metadata_metrics(params, content.metrics),
+                )
+            }
+            Node::Symbol { name, is_visible } => {
+                let content = content.pop().expect("a variable has a single rule");
+                let metrics = symbol_metrics(is_visible, &content);
+                Resolution::new(
+                    cost_model,
+                    Derivation::Symbol {
+                        name: name.to_string(),
+                        resolution: Arc::new(content),
+                    },
+                    metrics,
+                )
+            }
+        };
+        state.built.push(Some(resolution));
+    }
+}
+
+impl<'a> Symbols<'a> {
+    /// The entry of an interned symbol, borrowed from the symbol table rather than from `self`.
+    fn entry(&self, symbol: Symbol) -> Option<&'a SymbolEntry> {
+        match self {
+            Symbols::Named(_) => None,
+            Symbols::Interned { symbol_table, .. } => symbol_table.get(symbol),
+        }
+    }
+
+    /// The example of the variable at `index`.
+    fn example(&self, index: usize, name: &str) -> Option<&Arc<Resolution>> {
+        match self {
+            Symbols::Named(symbol_resolutions) => symbol_resolutions.get(name),
+            Symbols::Interned { symbol_table, .. } => {
+                symbol_table.non_terminals.get(index)?.resolution.as_ref()
+            }
+        }
+    }
+}
+
+impl SymbolLookup for Symbols<'_> {
+    fn named(&self, name: &str) -> Option<&Arc<Resolution>> {
+        match self {
+            Symbols::Named(symbol_resolutions) => symbol_resolutions.get(name),
+            Symbols::Interned { .. } => None,
+        }
+    }
+
+    fn interned(&self, symbol: Symbol) -> Option<&SymbolEntry> {
+        self.entry(symbol)
+    }
+
+    fn separator(&self, left: &TokenEdge, right: &TokenEdge) -> Option<&str> {
+        match self {
+            Symbols::Named(_) => None,
+            Symbols::Interned { boundaries, .. } => boundaries.separator(left, right),
+        }
+    }
+}
+
+impl State<'_> {
+    /// A lower bound on the derivations this one can be finished to.
+    fn minimum(&self) -> Minimum {
+        let built = self.built.iter().flatten().map(Minimum::of);
+        let pending = self.pending.iter().map(|item| match item {
+            Item::Rule(part) => part.minimum,
+            _ => Minimum::default(),
+        });
+        built.chain(pending).sum()
+    }
+}
+
+impl<'a> State<'a> {
+    /// Starts a node with the given items as its content, the first item last.
+    fn open(&mut self, node: Node<'a>, content: impl IntoIterator<Item = Item<'a>>) {
+        self.built.push(None);
+        self.pending.push(Item::Close(node));
+        self.pending.extend(content);
+    }
+}
+
+impl Minimum {
+    fn of(resolution: &Resolution) -> Self {
+        Minimum {
+            cost: resolution.cost,
+            length: resolution.metrics.length,
+        }
+    }
+
+    fn min(self, other: Minimum) -> Self {
+        Minimum {
+            cost: self.cost.min(other.cost),
+            length: self.length.min(other.length),
+        }
+    }
+}
+
+impl Add for Minimum {
+    type Output = Minimum;
+
+    fn add(self, other: Minimum) -> Minimum {
+        Minimum {
+            cost: self.cost + other.cost,
+            length: self.length + other.length,
+        }
+    }
+}
+
+impl Sum for Minimum {
+    fn sum<I: Iterator<Item = Minimum>>(iter: I) -> Minimum {
+        iter.fold(Minimum::default(), Add::add)
+    }
+}
+
+/// The cheapest partial derivation comes first in the max-heap, and the one queued first among
+/// those that cost the same.
+impl Ord for Queued<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.cost, other.serial).cmp(&(self.cost, self.serial))
+    }
+}
+
+impl PartialOrd for Queued<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Queued<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Queued<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{resolver::Resolver, tree_sitter_cli::parse_grammar::parse_grammar};
+
+    fn texts(resolver: &Resolver, name: &str, bound: SentenceBound) -> Vec<String> {
+        let resolutions = resolver.resolve();
+        resolutions
+            .sentences(name, bound)
+            .unwrap()
+            .map(|resolution| resolution.derivation.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_sentences() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "my_lang",
+            "extras": [{ "type": "PATTERN", "value": "\\s" }],
+            "word": "identifier",
+            "rules": {
+                "list": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "STRING", "value": "[" },
+                        {
+                            "type": "CHOICE",
+                            "members": [
+                                {
+                                    "type": "SEQ",
+                                    "members": [
+                                        { "type": "SYMBOL", "name": "_item" },
+                                        {
+                                            "type": "REPEAT",
+                                            "content": {
+                                                "type": "SEQ",
+                                                "members": [
+                                                    { "type": "STRING", "value": "," },
+                                                    { "type": "SYMBOL", "name": "_item" }
+                                                ]
+                                            }
+                                        }
+                                    ]
+                                },
+                                { "type": "BLANK" }
+                            ]
+                        },
+                        { "type": "STRING", "value": "]" }
+                    ]
+                },
+                "_item": {
+                    "type": "CHOICE",
+                    "members": [
+                        { "type": "SYMBOL", "name": "identifier" },
+                        { "type": "STRING", "value": "null" },
+                        { "type": "SYMBOL", "name": "list" }
+                    ]
+                },
+                "declaration": {
+                    "type": "SEQ",
+                    "members": [
+                        { "type": "STRING", "value": "let" },
+                        { "type": "SYMBOL", "name": "_item" }
+                    ]
+                },
+                "identifier": { "type": "PATTERN", "value": "[a-z]+" }
+            }
+        }"#,
+        )
+        .unwrap();
+        let resolver = Resolver::new(grammar);
+
+        let bound = SentenceBound {
+            max_depth: None,
+            max_length: Some(6),
+        };
+        assert_eq!(
+            texts(&resolver, "list", bound),
+            vec!["[]", "[a]", "[[]]", "[a,a]", "[[a]]", "[null]", "[[],a]", "[a,[]]", "[[[]]]"]
+        );
+
+        // Lists nested in the items of a list are left at their cheapest example.
+        let bound = SentenceBound {
+            max_depth: Some(1),
+            max_length: Some(7),
+        };
+        assert_eq!(
+            texts(&resolver, "list", bound),
+            vec!["[]", "[a]", "[[]]", "[a,a]", "[null]", "[a,[]]", "[[],a]", "[[],[]]", "[a,a,a]"]
+        );
+
+        let bound = SentenceBound {
+            max_depth: Some(1),
+            max_length: None,
+        };
+        assert_eq!(
+            texts(&resolver, "declaration", bound),
+            vec!["let a", "let[]", "let null"]
+        );
+        assert_eq!(
+            texts(&resolver, "identifier", SentenceBound::default()),
+            vec!["a"]
+        );
+        assert!(resolver.resolve().sentences("statement", bound).is_none());
+    }
+
+    #[test]
+    fn test_sentences_by_name() {
+        // The undefined symbol keeps the grammar from being prepared, so it is resolved by name.
+        let grammar = parse_grammar(
+            r#"{
+            "name": "my_lang",
+            "rules": {
+                "expression": {
+                    "type": "CHOICE",
+                    "members": [
+                        { "type": "SYMBOL", "name": "_value" },
+                        { "type": "STRING", "value": "x" },
+                        {
+                            "type": "SEQ",
+                            "members": [
+                                { "type": "SYMBOL", "name": "expression" },
+                                { "type": "STRING", "value": "+" },
+                                { "type": "SYMBOL", "name": "expression" }
+                            ]
+                        }
+                    ]
+                },
+                "_value": {
+                    "type": "CHOICE",
+                    "members": [
+                        { "type": "SYMBOL", "name": "expression" },
+                        { "type": "SYMBOL", "name": "undefined" }
+                    ]
+                }
+            }
+        }"#,
+        )
+        .unwrap();
+        let resolver = Resolver::new(grammar);
+        let warnings: Vec<String> = resolver
+            .resolve()
+            .warnings()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "Resolving by name, without separating tokens, repeating content or avoiding \
+                 keywords, as tree-sitter would reject the grammar: Undefined symbol `undefined` \
+                 in `_value`"
+            ]
+        );
+
+        // `_value` derives `expression` again without adding to the cost, which only the depth
+        // bound keeps from going on forever.
+        let bound = SentenceBound {
+            max_depth: Some(3),
+            max_length: Some(5),
+        };
+        assert_eq!(
+            texts(&resolver, "expression", bound),
+            vec!["x", "x+x", "x+x+x"]
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use std::{cell::RefCell, cmp::Reverse, collections::HashMap};
+
+use crate::{
+    derivation::TokenEdge,
+    tree_sitter_cli::{
+        dfa::Dfa,
+        grammars::{LexicalGrammar, Variable, VariableType},
+        nfa::{readability, CharacterSet, NfaCursor},
+        prepare_grammar::{expand_tokens, ExtractedLexicalGrammar},
+        rules::Rule,
+    },
+};
+
+/// Decides which adjacent tokens of an example the lexer would read as one, like `let` and `a`
+/// as the identifier `leta`, and separates them.
+///
+/// Without the parse table, it is not known which tokens the lexer tries at a given position.
+/// Instead, every token is tried that could be valid there: those that don't win over the token
+/// that is expected there on its own text.
+#[derive(Debug)]
+pub(crate) struct TokenBoundaries<'a> {
+    lexical_grammar: &'a LexicalGrammar,
+    /// What to put between tokens that would merge. Nothing is separated without one.
+    separator: Option<String>,
+    /// Whether each token is an `immediate_token`, which must not be preceded by a separator.
+    is_immediate: Vec<bool>,
+    /// The characters each token can start with.
+    first_chars: Vec<CharacterSet>,
+    /// The start states of the tokens that could be valid where a token with a text is expected.
+    rivals: RefCell<HashMap<(usize, String), Vec<u32>>>,
+    /// Whether a token with a text would merge with the text following it.
+    merges: RefCell<HashMap<(usize, String, String), bool>>,
+    /// The lexers for the tokens starting at each set of start states.
+    lexers: RefCell<HashMap<Vec<u32>, Dfa>>,
+}
+
+impl<'a> TokenBoundaries<'a> {
+    pub(crate) fn new(
+        lexical_grammar: &'a LexicalGrammar,
+        tokens: &ExtractedLexicalGrammar,
+        separator: Option<String>,
+    ) -> Self {
+        let is_immediate = tokens
+            .variables
+            .iter()
+            .map(|token| match &token.rule {
+                Rule::Metadata { params, .. } => params.is_main_token,
+                _ => false,
+            })
+            .collect();
+        let first_chars = lexical_grammar
+            .variables
+            .iter()
+            .map(|variable| first_chars(lexical_grammar, variable.start_state))
+            .collect();
+        TokenBoundaries {
+            lexical_grammar,
+            separator,
+            is_immediate,
+            first_chars,
+            rivals: RefCell::default(),
+            merges: RefCell::default(),
+            lexers: RefCell::default(),
+        }
+    }
+
+    /// The separator to put between two adjacent tokens, if the lexer would read them as one
+    /// otherwise. Tokens of unknown kind are left alone, and so are immediate tokens, which the
+    /// grammar requires to follow the previous token directly.
+    pub(crate) fn separator(&self, left: &TokenEdge, right: &TokenEdge) -> Option<&str> {
+        let separator = self.separator.as_deref()?;
+        let index = left.index?;
+        if right.index.is_some_and(|index| self.is_immediate[index]) {
+            return None;
+        }
+
+        let key = (index, left.text.clone(), right.text.clone());
+        let merges = *self
+            .merges
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| self.needs_separator(index, &left.text, separator, &right.text));
+        merges.then_some(separator)
+    }
+
+    fn needs_separator(&self, index: usize, left: &str, separator: &str, right: &str) -> bool {
+        // Tokens that would run past `left` even after a separator, like a catch-all for text,
+        // can't be what the lexer tries there if the example is to be lexed at all.
+        let start_state = self.lexical_grammar.variables[index].start_state;
+        let rivals: Vec<u32> = self
+            .rivals(index, left)
+            .into_iter()
+            .filter(|state| {
+                *state == start_state
+                    || !self.would_merge(vec![start_state, *state], left, separator, right)
+            })
+            .collect();
+        self.would_merge(rivals.clone(), left, "", right)
+            && !self.would_merge(rivals, left, separator, right)
+    }
+
+    /// Whether lexing `left`, `separator` and `right` with the tokens starting at `states` runs
+    /// past the end of `left`.
+    fn would_merge(&self, states: Vec<u32>, left: &str, separator: &str, right: &str) -> bool {
+        let text = format!("{left}{separator}{right}");
+        self.lexers
+            .borrow_mut()
+            .entry(states)
+            .or_insert_with_key(|states| Dfa::new(self.lexical_grammar, states.clone()))
+            .longest_match(&text)
+            .is_some_and(|(length, _, _)| length > left.len())
+    }
+
+    /// The start states of the token at `index` and of every token starting like `text` that
+    /// would not win over it on `text`. None if the token does not match `text` itself.
+    fn rivals(&self, index: usize, text: &str) -> Vec<u32> {
+        let key = (index, text.to_string());
+        if let Some(rivals) = self.rivals.borrow().get(&key) {
+            return rivals.clone();
+        }
+
+        let variables = &self.lexical_grammar.variables;
+        let completion = |i: usize| {
+            completion_precedence(self.lexical_grammar, variables[i].start_state, text)
+                .map(|precedence| (precedence, variables[i].implicit_precedence, Reverse(i)))
+        };
+        let rivals = match completion(index) {
+            Some(expected) => {
+                let first_char = text.chars().next().unwrap_or_default();
+                variables
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| {
+                        *i == index
+                            || self.first_chars[*i].contains(first_char)
+                                && completion(*i).is_none_or(|preference| preference < expected)
+                    })
+                    .map(|(_, variable)| variable.start_state)
+                    .collect()
+            }
+            None => vec![],
+        };
+        self.rivals.borrow_mut().insert(key, rivals.clone());
+        rivals
+    }
+}
+
+/// Picks the shortest example of an extra that can go between any two tokens, preferring the
+/// more readable ones, such as a space over a newline. An extra qualifies if the lexer ends it
+/// where its example ends whatever token follows, like a block comment but unlike a line comment.
+///
+/// `extras` are the rules of the extras along with their examples, and `extra_tokens` the indices
+/// of the extras that are tokens of the lexical grammar.
+pub(crate) fn shortest_separator(
+    lexical_grammar: &LexicalGrammar,
+    extra_tokens: &[usize],
+    extras: Vec<(&Rule, String)>,
+) -> Option<String> {
+    let following_chars = lexical_grammar
+        .variables
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !extra_tokens.contains(i))
+        .fold(CharacterSet::empty(), |chars, (_, variable)| {
+            chars.add(&first_chars(lexical_grammar, variable.start_state))
+        });
+    extras
+        .into_iter()
+        .filter(|(rule, example)| !example.is_empty() && is_closed(rule, example, &following_chars))
+        .map(|(_, example)| example)
+        .min_by_key(|example| {
+            let readability: Vec<u8> = example.chars().map(readability).collect();
+            (example.chars().count(), readability)
+        })
+}
+
+/// Whether `rule` matches all of `example`, and can't go on with any of `following_chars`.
+fn is_closed(rule: &Rule, example: &str, following_chars: &CharacterSet) -> bool {
+    let Ok(grammar) = expand_tokens(&ExtractedLexicalGrammar {
+        variables: vec![Variable {
+            name: "extra".to_string(),
+            kind: VariableType::Hidden,
+            rule: rule.clone(),
+        }],
+        separators: vec![],
+    }) else {
+        return false;
+    };
+    let mut cursor = NfaCursor::new(&grammar.nfa, vec![grammar.variables[0].start_state]);
+    advance(&mut cursor, example).is_some()
+        && cursor.completions().next().is_some()
+        && cursor
+            .transitions()
+            .iter()
+            .all(|transition| !transition.characters.does_intersect(following_chars))
+}
+
+/// The characters the token starting at `start_state` can start with.
+fn first_chars(lexical_grammar: &LexicalGrammar, start_state: u32) -> CharacterSet {
+    NfaCursor::new(&lexical_grammar.nfa, vec![start_state])
+        .transitions()
+        .into_iter()
+        .filter(|transition| !transition.is_separator)
+        .fold(CharacterSet::empty(), |chars, transition| {
+            chars.add(&transition.characters)
+        })
+}
+
+/// The precedence the token starting at `start_state` completes with after matching all of
+/// `text`, if it does.
+fn completion_precedence(
+    lexical_grammar: &LexicalGrammar,
+    start_state: u32,
+    text: &str,
+) -> Option<i32> {
+    let mut cursor = NfaCursor::new(&lexical_grammar.nfa, vec![start_state]);
+    advance(&mut cursor, text)?;
+    cursor.completions().map(|(_, precedence)| precedence).max()
+}
+
+/// Moves the cursor over `text`, leaving out the separators that may precede a token. `None` if
+/// the cursor's tokens don't match `text`.
+fn advance(cursor: &mut NfaCursor, text: &str) -> Option<()> {
+    for c in text.chars() {
+        let transition = cursor
+            .transitions()
+            .into_iter()
+            .find(|transition| !transition.is_separator && transition.characters.contains(c))?;
+        cursor.reset(transition.states);
+    }
+    Some(())
+}
@@ -0,0 +1,286 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashSet, VecDeque},
+    fmt,
+};
+
+use serde::Serialize;
+
+use crate::tree_sitter_cli::{
+    dfa::Dfa,
+    grammars::LexicalGrammar,
+    nfa::{readability, CharacterSet, NfaCursor},
+};
+
+/// Two tokens that match the same string, and which of them the lexer picks.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TokenOverlap {
+    pub winner: String,
+    pub loser: String,
+    /// The shortest string both tokens match.
+    pub witness: String,
+    pub reason: OverlapReason,
+    /// A longer string starting with `witness` that the loser still wins, because the lexer
+    /// prefers the longest match. `None` if the winner's precedence rules that out.
+    pub loser_wins_on: Option<String>,
+}
+
+/// Why the lexer picks one of two tokens matching the same string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlapReason {
+    /// The winner has a higher `prec`.
+    Precedence,
+    /// Both have the same `prec`, and tree-sitter prefers the winner's kind of token: strings
+    /// over patterns, and immediate tokens over the rest.
+    ImplicitPrecedence,
+    /// The tokens are otherwise equal, and the winner is declared first.
+    Order,
+}
+
+/// The states of two tokens matched side by side.
+pub(crate) type StatePair = (Vec<u32>, Vec<u32>);
+
+/// Lists every pair of tokens of the lexical grammar that can match the same string.
+pub(crate) fn token_overlaps(grammar: &LexicalGrammar) -> Vec<TokenOverlap> {
+    // Tokens can only overlap if they start with a common character, which rules out most pairs
+    // up front.
+    let first_chars: Vec<CharacterSet> = grammar
+        .variables
+        .iter()
+        .map(|variable| {
+            token_transitions(&NfaCursor::new(&grammar.nfa, vec![variable.start_state]))
+                .into_iter()
+                .fold(CharacterSet::empty(), |chars, (characters, _)| {
+                    chars.add(&characters)
+                })
+        })
+        .collect();
+
+    let mut overlaps = vec![];
+    for (i, first) in grammar.variables.iter().enumerate() {
+        for (j, second) in grammar.variables.iter().enumerate().skip(i + 1) {
+            if !first_chars[i].does_intersect(&first_chars[j]) {
+                continue;
+            }
+            let starts = (vec![first.start_state], vec![second.start_state]);
+            let Some((witness, (first_states, second_states))) =
+                search(grammar, starts, String::new(), false, |first, second, _| {
+                    first.completions().next().is_some() && second.completions().next().is_some()
+                })
+            else {
+                continue;
+            };
+
+            let precedence = |states: &Vec<u32>| {
+                let cursor = NfaCursor::new(&grammar.nfa, states.clone());
+                let (_, precedence) = cursor.completions().next().unwrap();
+                precedence
+            };
+            let (first_precedence, second_precedence) =
+                (precedence(&first_states), precedence(&second_states));
+            let first_key = (first_precedence, first.implicit_precedence, Reverse(i));
+            let second_key = (second_precedence, second.implicit_precedence, Reverse(j));
+            let reason = if first_precedence != second_precedence {
+                OverlapReason::Precedence
+            } else if first.implicit_precedence != second.implicit_precedence {
+                OverlapReason::ImplicitPrecedence
+            } else {
+                OverlapReason::Order
+            };
+            let (winner, loser, states) = if first_key > second_key {
+                (i, j, (second_states, first_states))
+            } else {
+                (j, i, (first_states, second_states))
+            };
+
+            // Continue from the witness for a longer string that only the loser matches, and
+            // check that the lexer does not stop at the winner's match on the way there.
+            let both = Dfa::new(
+                grammar,
+                vec![
+                    grammar.variables[winner].start_state,
+                    grammar.variables[loser].start_state,
+                ],
+            );
+            let loser_wins_on = search(
+                grammar,
+                states,
+                witness.clone(),
+                true,
+                |loser_cursor, winner_cursor, string| {
+                    string.len() > witness.len()
+                        && loser_cursor.completions().next().is_some()
+                        && winner_cursor.completions().next().is_none()
+                        && both
+                            .longest_match(string)
+                            .is_some_and(|(length, index, _)| {
+                                length == string.len() && index == loser
+                            })
+                },
+            )
+            .map(|(string, _)| string);
+
+            overlaps.push(TokenOverlap {
+                winner: grammar.variables[winner].name.clone(),
+                loser: grammar.variables[loser].name.clone(),
+                witness,
+                reason,
+                loser_wins_on,
+            });
+        }
+    }
+    overlaps
+}
+
+/// Breadth-first search over two tokens at once, starting from their states after `prefix`, for
+/// the shortest string extending `prefix` that `is_goal` accepts. The first token has to match
+/// every string along the way, and so does the second one unless `second_may_stop`. Of the
+/// strings that are equally short, the one made of the most readable characters is picked.
+///
+/// Returns the string along with the states both tokens end up in.
+pub(crate) fn search(
+    grammar: &LexicalGrammar,
+    (first_states, second_states): StatePair,
+    prefix: String,
+    second_may_stop: bool,
+    mut is_goal: impl FnMut(&NfaCursor, &NfaCursor, &str) -> bool,
+) -> Option<(String, StatePair)> {
+    let start = (
+        NfaCursor::new(&grammar.nfa, first_states).state_ids,
+        NfaCursor::new(&grammar.nfa, second_states).state_ids,
+    );
+    let mut first = NfaCursor::new(&grammar.nfa, vec![]);
+    let mut second = NfaCursor::new(&grammar.nfa, vec![]);
+    let mut visited = HashSet::from([start.clone()]);
+    let mut queue = VecDeque::from([(start, prefix)]);
+
+    while let Some((states, string)) = queue.pop_front() {
+        first.force_reset(states.0.clone());
+        second.force_reset(states.1.clone());
+        if is_goal(&first, &second, &string) {
+            return Some((string, states));
+        }
+
+        let mut transitions = vec![];
+        for (mut first_chars, first_states) in token_transitions(&first) {
+            for (mut second_chars, second_states) in token_transitions(&second) {
+                if !first_chars.does_intersect(&second_chars) {
+                    continue;
+                }
+                let intersection = first_chars.remove_intersection(&mut second_chars);
+                if let Some(c) = intersection.preferred_char() {
+                    transitions.push((c, first_states.clone(), second_states));
+                }
+            }
+            // What is left of the first token's characters, the second token cannot match.
+            if second_may_stop {
+                if let Some(c) = first_chars.preferred_char() {
+                    transitions.push((c, first_states, vec![]));
+                }
+            }
+        }
+        transitions.sort_by_key(|(c, _, _)| (readability(*c), *c));
+        for (c, first_states, second_states) in transitions {
+            let next = (
+                NfaCursor::new(&grammar.nfa, first_states).state_ids,
+                NfaCursor::new(&grammar.nfa, second_states).state_ids,
+            );
+            if visited.insert(next.clone()) {
+                let mut next_string = string.clone();
+                next_string.push(c);
+                queue.push_back((next, next_string));
+            }
+        }
+    }
+    None
+}
+
+/// The transitions of a token's states, leaving out the separators that may precede it.
+fn token_transitions(cursor: &NfaCursor) -> Vec<(CharacterSet, Vec<u32>)> {
+    cursor
+        .transitions()
+        .into_iter()
+        .filter(|transition| !transition.is_separator)
+        .map(|transition| (transition.characters, transition.states))
+        .collect()
+}
+
+impl fmt::Display for OverlapReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverlapReason::Precedence => write!(f, "precedence"),
+            OverlapReason::ImplicitPrecedence => write!(f, "implicit precedence"),
+            OverlapReason::Order => write!(f, "order"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{resolver::Resolver, tree_sitter_cli::parse_grammar::parse_grammar};
+
+    #[test]
+    fn test_token_overlaps() {
+        let grammar = parse_grammar(
+            r#"{
+            "name": "my_lang",
+            "rules": {
+                "program": {
+                    "type": "CHOICE",
+                    "members": [
+                        { "type": "STRING", "value": "if" },
+                        { "type": "SYMBOL", "name": "identifier" },
+                        { "type": "SYMBOL", "name": "number" },
+                        { "type": "SYMBOL", "name": "hex" },
+                        { "type": "SYMBOL", "name": "do" },
+                        { "type": "STRING", "value": "+" }
+                    ]
+                },
+                "identifier": { "type": "PATTERN", "value": "[a-z]+" },
+                "number": { "type": "PATTERN", "value": "\\d+" },
+                "hex": { "type": "PATTERN", "value": "[\\da-f]+" },
+                "do": {
+                    "type": "TOKEN",
+                    "content": {
+                        "type": "PREC",
+                        "value": 1,
+                        "content": { "type": "STRING", "value": "do" }
+                    }
+                }
+            }
+        }"#,
+        )
+        .unwrap();
+
+        let overlaps = Resolver::new(grammar).token_overlaps().unwrap();
+        let overlap =
+            |winner: &str, loser: &str, witness: &str, reason, loser_wins_on: Option<&str>| {
+                TokenOverlap {
+                    winner: winner.to_string(),
+                    loser: loser.to_string(),
+                    witness: witness.to_string(),
+                    reason,
+                    loser_wins_on: loser_wins_on.map(str::to_string),
+                }
+            };
+        assert_eq!(
+            overlaps,
+            vec![
+                // Keywords win over identifiers, which only take over once they are longer.
+                overlap(
+                    "if",
+                    "identifier",
+                    "if",
+                    OverlapReason::ImplicitPrecedence,
+                    Some("ifa")
+                ),
+                overlap("identifier", "hex", "a", OverlapReason::Order, Some("a0")),
+                // The lexer does not look past a match with a higher precedence.
+                overlap("do", "identifier", "do", OverlapReason::Precedence, None),
+                overlap("number", "hex", "0", OverlapReason::Order, Some("0a")),
+            ]
+        );
+    }
+}
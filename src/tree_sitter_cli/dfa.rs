@@ -0,0 +1,417 @@
+use super::grammars::LexicalGrammar;
+use super::nfa::{CharacterSet, NfaCursor};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem;
+
+/// A deterministic automaton built from the states of a lexical grammar's `Nfa`, which picks the
+/// same token as tree-sitter's lexer among the tokens it starts from. It does not model the lex
+/// states tree-sitter derives from the parse table, nor its keyword extraction. State 0 is the
+/// start state.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Dfa {
+    pub states: Vec<DfaState>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DfaState {
+    /// The token completed in this state as a variable index and precedence, if any.
+    pub accept: Option<(usize, i32)>,
+    /// Transitions with disjoint character sets, sorted by their characters.
+    pub transitions: Vec<DfaTransition>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DfaTransition {
+    pub characters: CharacterSet,
+    pub is_separator: bool,
+    pub state: usize,
+}
+
+impl Dfa {
+    /// Builds the automaton by subset construction from the given NFA states, then minimizes it.
+    ///
+    /// Like tree-sitter's lexer, a state completing a token only keeps the transitions whose
+    /// precedence is at least that token's. Of the tokens completed in the same state, the
+    /// preferred one wins, see `NfaCursor::preferred_completion`.
+    pub fn new(grammar: &LexicalGrammar, start_states: Vec<u32>) -> Self {
+        Self::from_subsets(grammar, start_states).minimize()
+    }
+
+    fn from_subsets(grammar: &LexicalGrammar, start_states: Vec<u32>) -> Self {
+        let nfa = &grammar.nfa;
+        let mut cursor = NfaCursor::new(nfa, start_states);
+        let mut state_ids_by_subset = HashMap::new();
+        let mut subsets = vec![cursor.state_ids.clone()];
+        state_ids_by_subset.insert(cursor.state_ids.clone(), 0);
+
+        let mut states = Vec::new();
+        while states.len() < subsets.len() {
+            cursor.force_reset(subsets[states.len()].clone());
+            let accept = cursor.preferred_completion(&grammar.variables);
+
+            let mut transitions = Vec::new();
+            for transition in cursor.transitions() {
+                if accept.is_some_and(|(_, precedence)| transition.precedence < precedence) {
+                    continue;
+                }
+                let subset = NfaCursor::new(nfa, transition.states).state_ids;
+                let state = *state_ids_by_subset
+                    .entry(subset)
+                    .or_insert_with_key(|subset| {
+                        subsets.push(subset.clone());
+                        subsets.len() - 1
+                    });
+                transitions.push(DfaTransition {
+                    characters: transition.characters,
+                    is_separator: transition.is_separator,
+                    state,
+                });
+            }
+            states.push(DfaState {
+                accept,
+                transitions,
+            });
+        }
+        Self { states }
+    }
+
+    /// Merges equivalent states with Hopcroft's partition refinement, and drops the states from
+    /// which no token can be completed.
+    fn minimize(self) -> Self {
+        // Split the characters into classes that no transition tells apart, so that every state
+        // has at most one transition per class. Every class is a symbol of the alphabet twice:
+        // once for separator transitions, and once for the rest.
+        let mut classes: Vec<CharacterSet> = Vec::new();
+        for transition in self.states.iter().flat_map(|state| &state.transitions) {
+            let mut characters = transition.characters.clone();
+            let mut i = 0;
+            while i < classes.len() && !characters.is_empty() {
+                let intersection = classes[i].remove_intersection(&mut characters);
+                if !intersection.is_empty() {
+                    if classes[i].is_empty() {
+                        classes[i] = intersection;
+                    } else {
+                        classes.push(intersection);
+                    }
+                }
+                i += 1;
+            }
+            if !characters.is_empty() {
+                classes.push(characters);
+            }
+        }
+        let symbol_count = classes.len() * 2;
+
+        // Complete the automaton with a dead state, and index the transitions backwards.
+        let dead_state = self.states.len();
+        let state_count = self.states.len() + 1;
+        let mut targets = vec![dead_state; state_count * symbol_count];
+        for (i, state) in self.states.iter().enumerate() {
+            for transition in &state.transitions {
+                for (class_index, class) in classes.iter().enumerate() {
+                    if transition.characters.does_intersect(class) {
+                        let symbol = class_index * 2 + transition.is_separator as usize;
+                        targets[i * symbol_count + symbol] = transition.state;
+                    }
+                }
+            }
+        }
+        let mut sources = vec![Vec::new(); state_count * symbol_count];
+        for (i, target) in targets.iter().enumerate() {
+            let (state, symbol) = (i / symbol_count, i % symbol_count);
+            sources[target * symbol_count + symbol].push(state);
+        }
+
+        // Start with the states grouped by the token they complete.
+        let mut blocks: Vec<Vec<usize>> = Vec::new();
+        let mut block_ids_by_accept = HashMap::new();
+        let mut state_blocks = vec![0; state_count];
+        for (state, state_block) in state_blocks.iter_mut().enumerate() {
+            let accept = self.states.get(state).and_then(|state| state.accept);
+            let block = *block_ids_by_accept.entry(accept).or_insert_with(|| {
+                blocks.push(Vec::new());
+                blocks.len() - 1
+            });
+            blocks[block].push(state);
+            *state_block = block;
+        }
+
+        let mut pending: HashSet<(usize, usize)> = HashSet::new();
+        let mut worklist = Vec::new();
+        for block in 0..blocks.len() {
+            for symbol in 0..symbol_count {
+                pending.insert((block, symbol));
+                worklist.push((block, symbol));
+            }
+        }
+
+        let mut is_predecessor = vec![false; state_count];
+        while let Some((splitter, symbol)) = worklist.pop() {
+            pending.remove(&(splitter, symbol));
+            let mut predecessors = Vec::new();
+            for state in &blocks[splitter] {
+                for predecessor in &sources[state * symbol_count + symbol] {
+                    if !is_predecessor[*predecessor] {
+                        is_predecessor[*predecessor] = true;
+                        predecessors.push(*predecessor);
+                    }
+                }
+            }
+
+            let mut touched_blocks: Vec<usize> = predecessors
+                .iter()
+                .map(|state| state_blocks[*state])
+                .collect();
+            touched_blocks.sort_unstable();
+            touched_blocks.dedup();
+            for block in touched_blocks {
+                let (inside, outside): (Vec<usize>, Vec<usize>) = blocks[block]
+                    .iter()
+                    .partition(|state| is_predecessor[**state]);
+                if outside.is_empty() {
+                    continue;
+                }
+
+                let new_block = blocks.len();
+                for state in &outside {
+                    state_blocks[*state] = new_block;
+                }
+                let inside_len = inside.len();
+                blocks[block] = inside;
+                blocks.push(outside);
+                for symbol in 0..symbol_count {
+                    if pending.contains(&(block, symbol)) || blocks[new_block].len() <= inside_len {
+                        pending.insert((new_block, symbol));
+                        worklist.push((new_block, symbol));
+                    } else {
+                        pending.insert((block, symbol));
+                        worklist.push((block, symbol));
+                    }
+                }
+            }
+
+            for state in predecessors {
+                is_predecessor[state] = false;
+            }
+        }
+
+        // Renumber the remaining blocks in breadth-first order from the start state.
+        let dead_block = state_blocks[dead_state];
+        let mut new_ids = vec![None; blocks.len()];
+        let mut order = Vec::new();
+        let mut queue = VecDeque::new();
+        if state_blocks[0] != dead_block {
+            new_ids[state_blocks[0]] = Some(0);
+            order.push(state_blocks[0]);
+            queue.push_back(state_blocks[0]);
+        }
+        while let Some(block) = queue.pop_front() {
+            for transition in &self.states[blocks[block][0]].transitions {
+                let target = state_blocks[transition.state];
+                if target != dead_block && new_ids[target].is_none() {
+                    new_ids[target] = Some(order.len());
+                    order.push(target);
+                    queue.push_back(target);
+                }
+            }
+        }
+
+        let mut old_states: Vec<Option<DfaState>> = self.states.into_iter().map(Some).collect();
+        let mut states = Vec::new();
+        for block in order {
+            let state = old_states[blocks[block][0]].take().unwrap();
+            let mut transitions: Vec<DfaTransition> = Vec::new();
+            for transition in state.transitions {
+                let Some(target) = new_ids[state_blocks[transition.state]] else {
+                    continue;
+                };
+                if let Some(existing) = transitions
+                    .iter_mut()
+                    .find(|t| t.state == target && t.is_separator == transition.is_separator)
+                {
+                    let characters = mem::replace(&mut existing.characters, CharacterSet::empty());
+                    existing.characters = characters.add(&transition.characters);
+                } else {
+                    transitions.push(DfaTransition {
+                        characters: transition.characters,
+                        is_separator: transition.is_separator,
+                        state: target,
+                    });
+                }
+            }
+            transitions.sort_unstable_by(|a, b| a.characters.cmp(&b.characters));
+            states.push(DfaState {
+                accept: state.accept,
+                transitions,
+            });
+        }
+        Self { states }
+    }
+
+    /// Whether the automaton accepts the whole of `s`.
+    pub fn matches(&self, s: &str) -> bool {
+        let Some(mut state) = self.states.first() else {
+            return false;
+        };
+        for c in s.chars() {
+            let Some(transition) = state.transitions.iter().find(|t| t.characters.contains(c))
+            else {
+                return false;
+            };
+            state = &self.states[transition.state];
+        }
+        state.accept.is_some()
+    }
+
+    /// Lexes a token at the start of `s`, returning the number of bytes consumed (separators
+    /// included) along with the index of the token's variable and its precedence.
+    pub fn longest_match(&self, s: &str) -> Option<(usize, usize, i32)> {
+        let mut state = self.states.first()?;
+        let mut result = state
+            .accept
+            .map(|(index, precedence)| (0, index, precedence));
+        let mut end = 0;
+        for c in s.chars() {
+            let Some(transition) = state.transitions.iter().find(|t| t.characters.contains(c))
+            else {
+                break;
+            };
+            state = &self.states[transition.state];
+            end += c.len_utf8();
+            if let Some((index, precedence)) = state.accept {
+                result = Some((end, index, precedence));
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree_sitter_cli::grammars::Variable;
+    use crate::tree_sitter_cli::prepare_grammar::{expand_tokens, ExtractedLexicalGrammar};
+    use crate::tree_sitter_cli::rules::{Precedence, Rule};
+
+    #[test]
+    fn test_minimized_state_counts() {
+        let table = [
+            (r"(a|b)*abb", 4),
+            (r"ab|cb", 3),
+            (r"a+|aa*", 2),
+            (r"[a-c]|[b-d]x?", 3),
+        ];
+        for (pattern, state_count) in table {
+            let grammar = expand_tokens(&ExtractedLexicalGrammar {
+                variables: vec![Variable::named("token", Rule::pattern(pattern))],
+                separators: vec![],
+            })
+            .unwrap();
+            let dfa = Dfa::new(&grammar, vec![grammar.variables[0].start_state]);
+            assert_eq!(dfa.states.len(), state_count, "states for /{}/", pattern);
+        }
+    }
+
+    #[test]
+    fn test_longest_match() {
+        let grammar = expand_tokens(&ExtractedLexicalGrammar {
+            variables: vec![
+                Variable::anonymous("if", Rule::string("if")),
+                Variable::named("identifier", Rule::pattern("[a-z]+")),
+                Variable::anonymous("a", Rule::prec(Precedence::Integer(1), Rule::string("a"))),
+                Variable::anonymous("ab", Rule::string("ab")),
+                Variable::named("number", Rule::pattern(r"\d+(\.\d+)?")),
+            ],
+            separators: vec![Rule::pattern(r"\s")],
+        })
+        .unwrap();
+        let start_states: Vec<u32> = grammar.variables.iter().map(|v| v.start_state).collect();
+        let dfa = Dfa::new(&grammar, start_states.clone());
+
+        assert_eq!(dfa.longest_match("iff("), Some((3, 1, 0)));
+        assert_eq!(dfa.longest_match("if("), Some((2, 0, 0)));
+        assert_eq!(dfa.longest_match(" \nif"), Some((4, 0, 0)));
+        assert_eq!(dfa.longest_match("ab"), Some((1, 2, 1)));
+        assert_eq!(dfa.longest_match("12.5."), Some((4, 4, 0)));
+        assert_eq!(dfa.longest_match("12."), Some((2, 4, 0)));
+        assert_eq!(dfa.longest_match("(if"), None);
+        assert!(dfa.matches("12.5"));
+        assert!(!dfa.matches("12."));
+
+        // The minimized automaton lexes like the one it was built from.
+        let subsets = Dfa::from_subsets(&grammar, start_states);
+        for s in ["if", "ifx", "a", "ab", "abc", " 1", "1.", "1.2", "x.y", ""] {
+            assert_eq!(
+                subsets.longest_match(s),
+                dfa.longest_match(s),
+                "lexing {:?}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn test_nfa_and_dfa_lex_alike() {
+        let grammar = expand_tokens(&ExtractedLexicalGrammar {
+            variables: vec![
+                Variable::anonymous("a", Rule::prec(Precedence::Integer(1), Rule::string("a"))),
+                Variable::anonymous(
+                    "abcd",
+                    Rule::seq(vec![
+                        Rule::prec(Precedence::Integer(1), Rule::string("ab")),
+                        Rule::string("cd"),
+                    ]),
+                ),
+                Variable::anonymous("ac", Rule::string("ac")),
+                Variable::named("identifier", Rule::pattern("[a-z]+")),
+            ],
+            separators: vec![Rule::pattern(r"\s")],
+        })
+        .unwrap();
+        let start_states: Vec<u32> = grammar.variables.iter().map(|v| v.start_state).collect();
+        let dfa = Dfa::new(&grammar, start_states.clone());
+        let cursor = NfaCursor::new(&grammar.nfa, start_states);
+
+        // Only where `a` is completed are the transitions below its precedence dropped, so `ac`
+        // loses to it, while past the `b` of `abcd` tokens with a lower precedence win again.
+        assert_eq!(dfa.longest_match("ac"), Some((1, 0, 1)));
+        assert_eq!(dfa.longest_match("ab"), Some((2, 3, 0)));
+        assert_eq!(dfa.longest_match("abcd"), Some((4, 1, 0)));
+        for s in [
+            "a", "ab", "abc", "abcd", "abcde", "ac", "b", "bcd", " abcd", "",
+        ] {
+            assert_eq!(
+                cursor.longest_match(s, &grammar.variables),
+                dfa.longest_match(s),
+                "lexing {:?}",
+                s
+            );
+        }
+
+        let grammar = expand_tokens(&ExtractedLexicalGrammar {
+            variables: vec![
+                Variable::named("identifier", Rule::pattern("[a-z]+")),
+                Variable::anonymous("if", Rule::string("if")),
+                Variable::named("immediate", Rule::immediate_token(Rule::pattern("[a-z]x"))),
+            ],
+            separators: vec![Rule::pattern(r"\s")],
+        })
+        .unwrap();
+        let start_states: Vec<u32> = grammar.variables.iter().map(|v| v.start_state).collect();
+        let dfa = Dfa::new(&grammar, start_states.clone());
+        let cursor = NfaCursor::new(&grammar.nfa, start_states);
+
+        // Strings and then immediate tokens win over patterns declared before them.
+        assert_eq!(dfa.longest_match("if"), Some((2, 1, 0)));
+        assert_eq!(dfa.longest_match("ax"), Some((2, 2, 0)));
+        assert_eq!(dfa.longest_match("ifx"), Some((3, 0, 0)));
+        for s in ["i", "if", "ifx", "ix", "ax", "axe", " if", ""] {
+            assert_eq!(
+                cursor.longest_match(s, &grammar.variables),
+                dfa.longest_match(s),
+                "lexing {:?}",
+                s
+            );
+        }
+    }
+}
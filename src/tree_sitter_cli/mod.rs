@@ -1,5 +1,24 @@
 #![allow(dead_code)]
-mod grammars;
-mod nfa;
+// Vendored from the tree-sitter CLI, kept close to upstream to ease syncing.
+#![allow(
+    clippy::clone_on_copy,
+    clippy::collapsible_if,
+    clippy::derivable_impls,
+    clippy::from_over_into,
+    clippy::into_iter_on_ref,
+    clippy::needless_borrow,
+    clippy::needless_return,
+    clippy::ptr_arg,
+    clippy::single_match,
+    clippy::single_range_in_vec_init,
+    clippy::unnecessary_cast,
+    clippy::unnecessary_mut_passed,
+    clippy::upper_case_acronyms,
+    clippy::write_with_newline
+)]
+pub mod dfa;
+pub mod grammars;
+pub mod nfa;
 pub mod parse_grammar;
+pub mod prepare_grammar;
 pub mod rules;
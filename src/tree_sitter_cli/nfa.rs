@@ -1,7 +1,9 @@
+use super::grammars::LexicalVariable;
 use std::char;
 use std::cmp::max;
 use std::cmp::Ordering;
-use std::collections::HashSet;
+use std::cmp::Reverse;
+use std::collections::{HashSet, VecDeque};
 use std::fmt;
 use std::mem::swap;
 use std::ops::Range;
@@ -55,6 +57,10 @@ impl Default for Nfa {
 
 const END: u32 = char::MAX as u32 + 1;
 
+/// How many characters of a set `CharacterSet::preferred_char` looks at. Negated sets contain
+/// most of unicode, and their readable characters come early anyway.
+const PREFERRED_CHAR_SEARCH_LIMIT: usize = 1024;
+
 impl CharacterSet {
     /// Create a character set with a single character.
     pub fn empty() -> Self {
@@ -341,6 +347,41 @@ impl CharacterSet {
     pub fn contains(&self, c: char) -> bool {
         self.ranges.iter().any(|r| r.contains(&(c as u32)))
     }
+
+    /// The most readable character in the set, for showing examples: ASCII letters and digits
+    /// come first, then ASCII punctuation, a space, other ASCII whitespace, letters from other
+    /// scripts, and only then control characters.
+    pub fn preferred_char(&self) -> Option<char> {
+        let mut result: Option<char> = None;
+        for c in self.chars().take(PREFERRED_CHAR_SEARCH_LIMIT) {
+            if result.is_none_or(|r| readability(c) < readability(r)) {
+                result = Some(c);
+                if readability(c) == 0 {
+                    break;
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Ranks characters from most (0) to least readable.
+pub(crate) fn readability(c: char) -> u8 {
+    if c.is_ascii_alphanumeric() {
+        0
+    } else if c.is_ascii_punctuation() {
+        1
+    } else if c == ' ' {
+        2
+    } else if c.is_ascii_whitespace() {
+        3
+    } else if c.is_alphanumeric() {
+        4
+    } else if !c.is_control() {
+        5
+    } else {
+        6
+    }
 }
 
 impl Ord for CharacterSet {
@@ -522,6 +563,109 @@ impl<'a> NfaCursor<'a> {
         result
     }
 
+    /// Breadth-first search over the sets of states reachable from the cursor's states, returning
+    /// the first (and therefore shortest) string that reaches an accepting state. Of the strings
+    /// that are equally short, the one made of the most readable characters is picked.
+    pub fn shortest_match(&self) -> Option<String> {
+        let mut cursor = NfaCursor {
+            nfa: self.nfa,
+            state_ids: self.state_ids.clone(),
+        };
+        let mut visited = HashSet::from([self.state_ids.clone()]);
+        let mut queue = VecDeque::from([(self.state_ids.clone(), String::new())]);
+
+        while let Some((state_ids, string)) = queue.pop_front() {
+            cursor.force_reset(state_ids);
+            if cursor.completions().next().is_some() {
+                return Some(string);
+            }
+            let mut transitions: Vec<(char, Vec<u32>)> = cursor
+                .transitions()
+                .into_iter()
+                .filter_map(|transition| {
+                    let c = transition.characters.preferred_char()?;
+                    Some((c, transition.states))
+                })
+                .collect();
+            transitions.sort_by_key(|(c, _)| (readability(*c), *c));
+            for (c, states) in transitions {
+                let next_cursor = NfaCursor::new(self.nfa, states);
+                if visited.insert(next_cursor.state_ids.clone()) {
+                    let mut next_string = string.clone();
+                    next_string.push(c);
+                    queue.push_back((next_cursor.state_ids, next_string));
+                }
+            }
+        }
+        None
+    }
+
+    /// Whether the cursor's states accept the whole of `s`.
+    pub fn matches(&self, s: &str) -> bool {
+        let mut cursor = NfaCursor {
+            nfa: self.nfa,
+            state_ids: self.state_ids.clone(),
+        };
+        for c in s.chars() {
+            if let Some(transition) = cursor
+                .transitions()
+                .into_iter()
+                .find(|t| t.characters.contains(c))
+            {
+                cursor.reset(transition.states);
+            } else {
+                return false;
+            }
+        }
+        cursor
+            .state_ids
+            .iter()
+            .any(|state_id| matches!(self.nfa.states[*state_id as usize], NfaState::Accept { .. }))
+    }
+
+    /// Lexes a token at the start of `s` the way tree-sitter's lexer would: the longest match
+    /// wins, except that where a token is completed, the transitions with a lower precedence than
+    /// that token's are dropped. Of the tokens completed at the same position, the preferred one
+    /// wins, see `preferred_completion`.
+    ///
+    /// Returns the number of bytes consumed, separators included, along with the index of the
+    /// token's variable and its precedence.
+    pub fn longest_match(
+        &self,
+        s: &str,
+        variables: &[LexicalVariable],
+    ) -> Option<(usize, usize, i32)> {
+        let mut cursor = NfaCursor {
+            nfa: self.nfa,
+            state_ids: self.state_ids.clone(),
+        };
+        let mut result: Option<(usize, usize, i32)> = None;
+        let mut end = 0;
+        let mut chars = s.chars();
+        loop {
+            let completion = cursor.preferred_completion(variables);
+            if let Some((index, precedence)) = completion {
+                result = Some((end, index, precedence));
+            }
+
+            let Some(c) = chars.next() else {
+                break;
+            };
+            let min_precedence = completion.map_or(i32::MIN, |(_, precedence)| precedence);
+            if let Some(transition) = cursor
+                .transitions()
+                .into_iter()
+                .find(|t| t.characters.contains(c) && t.precedence >= min_precedence)
+            {
+                cursor.reset(transition.states);
+                end += c.len_utf8();
+            } else {
+                break;
+            }
+        }
+        result
+    }
+
     pub fn completions(&self) -> impl Iterator<Item = (usize, i32)> + '_ {
         self.state_ids.iter().filter_map(move |state_id| {
             if let NfaState::Accept {
@@ -536,6 +680,19 @@ impl<'a> NfaCursor<'a> {
         })
     }
 
+    /// Of the tokens completed in the current states, the one tree-sitter's lexer picks: the one
+    /// with the highest precedence, then the highest implicit precedence, which favors strings
+    /// over patterns and immediate tokens over others, and then the one declared first.
+    pub fn preferred_completion(&self, variables: &[LexicalVariable]) -> Option<(usize, i32)> {
+        self.completions().max_by_key(|(index, precedence)| {
+            (
+                *precedence,
+                variables[*index].implicit_precedence,
+                Reverse(*index),
+            )
+        })
+    }
+
     pub fn add_states(&mut self, new_state_ids: &mut Vec<u32>) {
         let mut i = 0;
         while i < new_state_ids.len() {
@@ -569,6 +726,111 @@ impl<'a> NfaCursor<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tree_sitter_cli::grammars::{LexicalGrammar, Variable};
+    use crate::tree_sitter_cli::prepare_grammar::{expand_tokens, ExtractedLexicalGrammar};
+    use crate::tree_sitter_cli::rules::{Precedence, Rule};
+
+    #[test]
+    fn test_preferred_char() {
+        let table = [
+            (CharacterSet::from_range('\0', '\u{10FFFF}'), Some('0')),
+            (CharacterSet::from_char('\n').negate(), Some('0')),
+            (CharacterSet::from_range('a', 'z').add_char('!'), Some('a')),
+            (CharacterSet::from_range('\0', '/'), Some('!')),
+            (CharacterSet::from_range('\0', ' '), Some(' ')),
+            (CharacterSet::from_range('\0', '\t'), Some('\t')),
+            (CharacterSet::from_range('\u{80}', '\u{10FFFF}'), Some('ª')),
+            (CharacterSet::from_range('\u{80}', '\u{A0}'), Some('\u{A0}')),
+            (CharacterSet::from_range('\0', '\u{8}'), Some('\0')),
+            (CharacterSet::empty(), None),
+        ];
+        for (set, expected) in table {
+            assert_eq!(
+                set.preferred_char(),
+                expected,
+                "preferred char of {:?}",
+                set
+            );
+        }
+    }
+
+    #[test]
+    fn test_matches_and_longest_match() {
+        let grammar = expand_tokens(&ExtractedLexicalGrammar {
+            variables: vec![
+                Variable::anonymous("if", Rule::string("if")),
+                Variable::named("identifier", Rule::pattern("[a-z]+")),
+                Variable::anonymous("a", Rule::prec(Precedence::Integer(1), Rule::string("a"))),
+                Variable::anonymous("ab", Rule::string("ab")),
+            ],
+            separators: vec![Rule::pattern(" ")],
+        })
+        .unwrap();
+
+        assert!(cursor(&grammar, &[1]).matches("foo"));
+        assert!(!cursor(&grammar, &[1]).matches("foo1"));
+        assert!(!cursor(&grammar, &[1]).matches(""));
+        assert!(cursor(&grammar, &[0, 1]).matches(" if"));
+
+        // The longest match wins.
+        assert_eq!(
+            cursor(&grammar, &[0, 1]).longest_match("iff(", &grammar.variables),
+            Some((3, 1, 0))
+        );
+        // Of the tokens completed at the same position, the string wins over the pattern.
+        assert_eq!(
+            cursor(&grammar, &[0, 1]).longest_match("if(", &grammar.variables),
+            Some((2, 0, 0))
+        );
+        // Leading separators are consumed too.
+        assert_eq!(
+            cursor(&grammar, &[0, 1]).longest_match("  if", &grammar.variables),
+            Some((4, 0, 0))
+        );
+        // A completed token is not given up for a transition with a lower precedence.
+        assert_eq!(
+            cursor(&grammar, &[2, 3]).longest_match("ab", &grammar.variables),
+            Some((1, 2, 1))
+        );
+        assert_eq!(
+            cursor(&grammar, &[3]).longest_match("ab", &grammar.variables),
+            Some((2, 3, 0))
+        );
+        assert_eq!(
+            cursor(&grammar, &[0, 1]).longest_match("(if", &grammar.variables),
+            None
+        );
+    }
+
+    #[test]
+    fn test_longest_match_prefers_strings() {
+        let grammar = expand_tokens(&ExtractedLexicalGrammar {
+            variables: vec![
+                Variable::named("identifier", Rule::pattern("[a-z]+")),
+                Variable::anonymous("if", Rule::string("if")),
+            ],
+            separators: vec![],
+        })
+        .unwrap();
+
+        // A string wins over a pattern completed at the same position, even one declared first.
+        assert_eq!(
+            cursor(&grammar, &[0, 1]).longest_match("if", &grammar.variables),
+            Some((2, 1, 0))
+        );
+        assert_eq!(
+            cursor(&grammar, &[0, 1]).longest_match("iff", &grammar.variables),
+            Some((3, 0, 0))
+        );
+    }
+
+    fn cursor<'a>(grammar: &'a LexicalGrammar, indices: &[usize]) -> NfaCursor<'a> {
+        let start_states = indices
+            .iter()
+            .map(|i| grammar.variables[*i].start_state)
+            .collect();
+        NfaCursor::new(&grammar.nfa, start_states)
+    }
 
     #[test]
     fn test_adding_ranges() {
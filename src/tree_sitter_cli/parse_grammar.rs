@@ -89,7 +89,7 @@ pub(crate) struct GrammarJSON {
     word: Option<String>,
 }
 
-pub(crate) fn parse_grammar(input: &str) -> Result<InputGrammar> {
+pub fn parse_grammar(input: &str) -> Result<InputGrammar> {
     let grammar_json: GrammarJSON = serde_json::from_str(&input)?;
 
     let mut variables = Vec::with_capacity(grammar_json.rules.len());
@@ -0,0 +1,625 @@
+use super::super::grammars::{LexicalGrammar, LexicalVariable};
+use super::super::nfa::{CharacterSet, Nfa, NfaState};
+use super::super::rules::{Precedence, Rule};
+use super::ExtractedLexicalGrammar;
+use anyhow::{anyhow, Context, Result};
+use regex_syntax::ast::{
+    parse, Ast, ClassPerlKind, ClassSet, ClassSetBinaryOpKind, ClassSetItem, ClassUnicodeKind,
+    ClassUnicodeOpKind, RepetitionKind, RepetitionRange,
+};
+use regex_syntax::hir::{Class, HirKind};
+
+const ALLOWED_REDUNDANT_ESCAPED_CHARS: [char; 4] = ['!', '\'', '"', '/'];
+
+struct NfaBuilder {
+    nfa: Nfa,
+    is_sep: bool,
+    precedence_stack: Vec<i32>,
+}
+
+/// Compile a single regex into an NFA. The returned state id is the start state, and the
+/// only accepting state belongs to variable `0`.
+pub(crate) fn expand_pattern(pattern: &str) -> Result<(Nfa, u32)> {
+    let mut builder = NfaBuilder {
+        nfa: Nfa::new(),
+        is_sep: false,
+        precedence_stack: vec![0],
+    };
+    builder.nfa.states.push(NfaState::Accept {
+        variable_index: 0,
+        precedence: 0,
+    });
+    let last_state_id = builder.nfa.last_state_id();
+    builder
+        .expand_rule(&Rule::Pattern(pattern.to_string()), last_state_id)
+        .with_context(|| format!("Error processing pattern /{}/", pattern))?;
+    let start_state = builder.nfa.last_state_id();
+    Ok((builder.nfa, start_state))
+}
+
+/// Compile every token of the lexical grammar into one shared NFA. A token's start state is the
+/// last state added for it, and unless it is immediate, its accepting state is reached through
+/// the separators that may follow it.
+pub(crate) fn expand_tokens(grammar: &ExtractedLexicalGrammar) -> Result<LexicalGrammar> {
+    let mut builder = NfaBuilder {
+        nfa: Nfa::new(),
+        is_sep: true,
+        precedence_stack: vec![0],
+    };
+
+    let separator_rule = if grammar.separators.is_empty() {
+        Rule::Blank
+    } else {
+        let mut separators = grammar.separators.clone();
+        separators.push(Rule::Blank);
+        Rule::repeat(Rule::choice(separators))
+    };
+
+    let mut variables = Vec::with_capacity(grammar.variables.len());
+    for (i, variable) in grammar.variables.iter().enumerate() {
+        let is_immediate_token = match &variable.rule {
+            Rule::Metadata { params, .. } => params.is_main_token,
+            _ => false,
+        };
+
+        builder.is_sep = false;
+        builder.nfa.states.push(NfaState::Accept {
+            variable_index: i,
+            precedence: get_completion_precedence(&variable.rule),
+        });
+        let last_state_id = builder.nfa.last_state_id();
+        builder
+            .expand_rule(&variable.rule, last_state_id)
+            .with_context(|| format!("Error processing rule {}", variable.name))?;
+
+        if !is_immediate_token {
+            builder.is_sep = true;
+            let last_state_id = builder.nfa.last_state_id();
+            builder.expand_rule(&separator_rule, last_state_id)?;
+        }
+
+        variables.push(LexicalVariable {
+            name: variable.name.clone(),
+            kind: variable.kind,
+            implicit_precedence: get_implicit_precedence(&variable.rule),
+            start_state: builder.nfa.last_state_id(),
+        });
+    }
+
+    Ok(LexicalGrammar {
+        nfa: builder.nfa,
+        variables,
+    })
+}
+
+fn get_implicit_precedence(rule: &Rule) -> i32 {
+    match rule {
+        Rule::String(_) => 2,
+        Rule::Metadata { rule, params } => {
+            if params.is_main_token {
+                get_implicit_precedence(rule) + 1
+            } else {
+                get_implicit_precedence(rule)
+            }
+        }
+        _ => 0,
+    }
+}
+
+fn get_completion_precedence(rule: &Rule) -> i32 {
+    if let Rule::Metadata { params, .. } = rule {
+        if let Precedence::Integer(precedence) = params.precedence {
+            return precedence;
+        }
+    }
+    0
+}
+
+impl NfaBuilder {
+    fn expand_rule(&mut self, rule: &Rule, mut next_state_id: u32) -> Result<bool> {
+        match rule {
+            Rule::Pattern(s) => {
+                let s = preprocess_regex(s);
+                let ast = parse::Parser::new().parse(&s)?;
+                self.expand_regex(&ast, next_state_id)
+            }
+            Rule::String(s) => {
+                for c in s.chars().rev() {
+                    self.push_advance(CharacterSet::from_char(c), next_state_id);
+                    next_state_id = self.nfa.last_state_id();
+                }
+                Ok(!s.is_empty())
+            }
+            Rule::Choice(elements) => {
+                let mut alternative_state_ids = Vec::new();
+                for element in elements {
+                    if self.expand_rule(element, next_state_id)? {
+                        alternative_state_ids.push(self.nfa.last_state_id());
+                    } else {
+                        alternative_state_ids.push(next_state_id);
+                    }
+                }
+                alternative_state_ids.sort_unstable();
+                alternative_state_ids.dedup();
+                alternative_state_ids.retain(|i| *i != self.nfa.last_state_id());
+                for alternative_state_id in alternative_state_ids {
+                    self.push_split(alternative_state_id);
+                }
+                Ok(true)
+            }
+            Rule::Seq(elements) => {
+                let mut result = false;
+                for element in elements.iter().rev() {
+                    if self.expand_rule(element, next_state_id)? {
+                        result = true;
+                        next_state_id = self.nfa.last_state_id();
+                    }
+                }
+                Ok(result)
+            }
+            Rule::Repeat(rule) => {
+                self.nfa.states.push(NfaState::Accept {
+                    variable_index: 0,
+                    precedence: 0,
+                }); // Placeholder for split
+                let split_state_id = self.nfa.last_state_id();
+                if self.expand_rule(rule, split_state_id)? {
+                    self.nfa.states[split_state_id as usize] =
+                        NfaState::Split(self.nfa.last_state_id(), next_state_id);
+                    Ok(true)
+                } else {
+                    self.nfa.states.pop();
+                    Ok(false)
+                }
+            }
+            Rule::Metadata { rule, params } => {
+                let has_precedence = if let Precedence::Integer(precedence) = &params.precedence {
+                    self.precedence_stack.push(*precedence);
+                    true
+                } else {
+                    false
+                };
+                let result = self.expand_rule(rule, next_state_id);
+                if has_precedence {
+                    self.precedence_stack.pop();
+                }
+                result
+            }
+            Rule::Blank => Ok(false),
+            _ => Err(anyhow!("Grammar error: Unexpected rule {:?}", rule)),
+        }
+    }
+
+    fn expand_regex(&mut self, ast: &Ast, mut next_state_id: u32) -> Result<bool> {
+        match ast {
+            Ast::Empty(_) => Ok(false),
+            Ast::Flags(_) => Err(anyhow!("Regex error: Flags are not supported")),
+            Ast::Literal(literal) => {
+                self.push_advance(CharacterSet::from_char(literal.c), next_state_id);
+                Ok(true)
+            }
+            Ast::Dot(_) => {
+                self.push_advance(CharacterSet::from_char('\n').negate(), next_state_id);
+                Ok(true)
+            }
+            Ast::Assertion(_) => Err(anyhow!("Regex error: Assertions are not supported")),
+            Ast::ClassUnicode(class) => {
+                let mut chars = self.expand_unicode_character_class(&class.kind)?;
+                if class.negated {
+                    chars = chars.negate();
+                }
+                self.push_advance(chars, next_state_id);
+                Ok(true)
+            }
+            Ast::ClassPerl(class) => {
+                let mut chars = self.expand_perl_character_class(&class.kind);
+                if class.negated {
+                    chars = chars.negate();
+                }
+                self.push_advance(chars, next_state_id);
+                Ok(true)
+            }
+            Ast::ClassBracketed(class) => {
+                let mut chars = self.translate_class_set(&class.kind)?;
+                if class.negated {
+                    chars = chars.negate();
+                }
+                self.push_advance(chars, next_state_id);
+                Ok(true)
+            }
+            Ast::Repetition(repetition) => match repetition.op.kind {
+                RepetitionKind::ZeroOrOne => {
+                    self.expand_zero_or_one(&repetition.ast, next_state_id)
+                }
+                RepetitionKind::OneOrMore => {
+                    self.expand_one_or_more(&repetition.ast, next_state_id)
+                }
+                RepetitionKind::ZeroOrMore => {
+                    self.expand_zero_or_more(&repetition.ast, next_state_id)
+                }
+                RepetitionKind::Range(RepetitionRange::Exactly(count)) => {
+                    self.expand_count(&repetition.ast, count, next_state_id)
+                }
+                RepetitionKind::Range(RepetitionRange::AtLeast(min)) => {
+                    if self.expand_zero_or_more(&repetition.ast, next_state_id)? {
+                        let last_state_id = self.nfa.last_state_id();
+                        self.expand_count(&repetition.ast, min, last_state_id)?;
+                        Ok(true)
+                    } else {
+                        Ok(false)
+                    }
+                }
+                RepetitionKind::Range(RepetitionRange::Bounded(min, max)) => {
+                    let mut result = false;
+                    for _ in min..max {
+                        if self.expand_zero_or_one(&repetition.ast, next_state_id)? {
+                            result = true;
+                            next_state_id = self.nfa.last_state_id();
+                        }
+                    }
+                    if self.expand_count(&repetition.ast, min, next_state_id)? {
+                        result = true;
+                    }
+                    Ok(result)
+                }
+            },
+            Ast::Group(group) => self.expand_regex(&group.ast, next_state_id),
+            Ast::Alternation(alternation) => {
+                let mut alternative_state_ids = Vec::new();
+                for ast in alternation.asts.iter() {
+                    if self.expand_regex(ast, next_state_id)? {
+                        alternative_state_ids.push(self.nfa.last_state_id());
+                    } else {
+                        alternative_state_ids.push(next_state_id);
+                    }
+                }
+                alternative_state_ids.sort_unstable();
+                alternative_state_ids.dedup();
+                alternative_state_ids.retain(|i| *i != self.nfa.last_state_id());
+                for alternative_state_id in alternative_state_ids {
+                    self.push_split(alternative_state_id);
+                }
+                Ok(true)
+            }
+            Ast::Concat(concat) => {
+                let mut result = false;
+                for ast in concat.asts.iter().rev() {
+                    if self.expand_regex(ast, next_state_id)? {
+                        result = true;
+                        next_state_id = self.nfa.last_state_id();
+                    }
+                }
+                Ok(result)
+            }
+        }
+    }
+
+    fn translate_class_set(&self, class_set: &ClassSet) -> Result<CharacterSet> {
+        match &class_set {
+            ClassSet::Item(item) => self.expand_character_class(item),
+            ClassSet::BinaryOp(binary_op) => {
+                let mut lhs_char_class = self.translate_class_set(&binary_op.lhs)?;
+                let mut rhs_char_class = self.translate_class_set(&binary_op.rhs)?;
+                match binary_op.kind {
+                    ClassSetBinaryOpKind::Intersection => {
+                        Ok(lhs_char_class.remove_intersection(&mut rhs_char_class))
+                    }
+                    ClassSetBinaryOpKind::Difference => {
+                        Ok(lhs_char_class.difference(rhs_char_class))
+                    }
+                    ClassSetBinaryOpKind::SymmetricDifference => {
+                        Ok(lhs_char_class.symmetric_difference(rhs_char_class))
+                    }
+                }
+            }
+        }
+    }
+
+    fn expand_one_or_more(&mut self, ast: &Ast, next_state_id: u32) -> Result<bool> {
+        self.nfa.states.push(NfaState::Accept {
+            variable_index: 0,
+            precedence: 0,
+        }); // Placeholder for split
+        let split_state_id = self.nfa.last_state_id();
+        if self.expand_regex(ast, split_state_id)? {
+            self.nfa.states[split_state_id as usize] =
+                NfaState::Split(self.nfa.last_state_id(), next_state_id);
+            Ok(true)
+        } else {
+            self.nfa.states.pop();
+            Ok(false)
+        }
+    }
+
+    fn expand_zero_or_one(&mut self, ast: &Ast, next_state_id: u32) -> Result<bool> {
+        if self.expand_regex(ast, next_state_id)? {
+            self.push_split(next_state_id);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn expand_zero_or_more(&mut self, ast: &Ast, next_state_id: u32) -> Result<bool> {
+        if self.expand_one_or_more(ast, next_state_id)? {
+            self.push_split(next_state_id);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn expand_count(&mut self, ast: &Ast, count: u32, mut next_state_id: u32) -> Result<bool> {
+        let mut result = false;
+        for _ in 0..count {
+            if self.expand_regex(ast, next_state_id)? {
+                result = true;
+                next_state_id = self.nfa.last_state_id();
+            }
+        }
+        Ok(result)
+    }
+
+    fn expand_character_class(&self, item: &ClassSetItem) -> Result<CharacterSet> {
+        match item {
+            ClassSetItem::Empty(_) => Ok(CharacterSet::empty()),
+            ClassSetItem::Literal(literal) => Ok(CharacterSet::from_char(literal.c)),
+            ClassSetItem::Range(range) => Ok(CharacterSet::from_range(range.start.c, range.end.c)),
+            ClassSetItem::Union(union) => {
+                let mut result = CharacterSet::empty();
+                for item in &union.items {
+                    result = result.add(&self.expand_character_class(item)?);
+                }
+                Ok(result)
+            }
+            ClassSetItem::Perl(class) => {
+                let mut chars = self.expand_perl_character_class(&class.kind);
+                if class.negated {
+                    chars = chars.negate();
+                }
+                Ok(chars)
+            }
+            ClassSetItem::Unicode(class) => {
+                let mut chars = self.expand_unicode_character_class(&class.kind)?;
+                if class.negated {
+                    chars = chars.negate();
+                }
+                Ok(chars)
+            }
+            ClassSetItem::Bracketed(class) => {
+                let mut chars = self.translate_class_set(&class.kind)?;
+                if class.negated {
+                    chars = chars.negate();
+                }
+                Ok(chars)
+            }
+            _ => Err(anyhow!(
+                "Regex error: Unsupported character class syntax {:?}",
+                item
+            )),
+        }
+    }
+
+    fn expand_unicode_character_class(&self, class: &ClassUnicodeKind) -> Result<CharacterSet> {
+        // The unicode tables are only exposed through regex-syntax's translator, so the
+        // property is re-parsed on its own and its ranges are read back from the HIR.
+        let property = match class {
+            ClassUnicodeKind::OneLetter(letter) => letter.to_string(),
+            ClassUnicodeKind::Named(name) => name.clone(),
+            ClassUnicodeKind::NamedValue { op, name, value } => match op {
+                ClassUnicodeOpKind::NotEqual => format!("{}!={}", name, value),
+                _ => format!("{}={}", name, value),
+            },
+        };
+        let hir = regex_syntax::Parser::new()
+            .parse(&format!("\\p{{{}}}", property))
+            .with_context(|| format!("Regex error: Unsupported unicode class {}", property))?;
+        match hir.kind() {
+            HirKind::Class(Class::Unicode(class)) => Ok(class
+                .ranges()
+                .iter()
+                .fold(CharacterSet::empty(), |chars, range| {
+                    chars.add_range(range.start(), range.end())
+                })),
+            HirKind::Literal(literal) => Ok(String::from_utf8_lossy(&literal.0)
+                .chars()
+                .fold(CharacterSet::empty(), |chars, c| chars.add_char(c))),
+            _ => Err(anyhow!(
+                "Regex error: Unsupported unicode class {}",
+                property
+            )),
+        }
+    }
+
+    fn expand_perl_character_class(&self, item: &ClassPerlKind) -> CharacterSet {
+        match item {
+            ClassPerlKind::Digit => CharacterSet::from_range('0', '9'),
+            ClassPerlKind::Space => CharacterSet::empty()
+                .add_char(' ')
+                .add_char('\t')
+                .add_char('\r')
+                .add_char('\n')
+                .add_char('\x0B')
+                .add_char('\x0C'),
+            ClassPerlKind::Word => CharacterSet::empty()
+                .add_char('_')
+                .add_range('A', 'Z')
+                .add_range('a', 'z')
+                .add_range('0', '9'),
+        }
+    }
+
+    fn push_advance(&mut self, chars: CharacterSet, state_id: u32) {
+        let precedence = *self.precedence_stack.last().unwrap();
+        self.nfa.states.push(NfaState::Advance {
+            chars,
+            state_id,
+            precedence,
+            is_sep: self.is_sep,
+        });
+    }
+
+    fn push_split(&mut self, state_id: u32) {
+        let last_state_id = self.nfa.last_state_id();
+        self.nfa
+            .states
+            .push(NfaState::Split(state_id, last_state_id));
+    }
+}
+
+fn preprocess_regex(content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut is_escaped = false;
+    let mut previous_escape = None;
+    for (i, c) in content.char_indices() {
+        if is_escaped {
+            if !ALLOWED_REDUNDANT_ESCAPED_CHARS.contains(&c) {
+                result.push('\\');
+            }
+            result.push(c);
+            is_escaped = false;
+            previous_escape = Some(c);
+            continue;
+        }
+        if c == '\\' {
+            is_escaped = true;
+        } else if c == '{'
+            && !matches!(previous_escape, Some('p' | 'P' | 'u' | 'U' | 'x'))
+            && !is_repetition_range(&content[i + 1..])
+        {
+            // Javascript treats a brace that doesn't start a repetition as a literal.
+            result.push_str("\\{");
+        } else {
+            result.push(c);
+        }
+        previous_escape = None;
+    }
+    if is_escaped {
+        result.push('\\');
+    }
+    result
+}
+
+fn is_repetition_range(rest: &str) -> bool {
+    let Some(end) = rest.find('}') else {
+        return false;
+    };
+    let mut bounds = rest[..end].splitn(2, ',');
+    let min = bounds.next().unwrap_or_default();
+    let max = bounds.next().unwrap_or_default();
+    !min.is_empty()
+        && min.chars().all(|c| c.is_ascii_digit())
+        && max.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::grammars::{Variable, VariableType};
+    use super::super::super::nfa::NfaCursor;
+    use super::*;
+
+    /// Lexes the longest token at the start of `s`, returning its index and text without the
+    /// separators in front of it.
+    fn simulate_lexer<'a>(grammar: &LexicalGrammar, s: &'a str) -> Option<(usize, &'a str)> {
+        let start_states = grammar.variables.iter().map(|v| v.start_state).collect();
+        let (length, index, _) =
+            NfaCursor::new(&grammar.nfa, start_states).longest_match(s, &grammar.variables)?;
+        Some((index, s[..length].trim_start()))
+    }
+
+    #[test]
+    fn test_expand_tokens() {
+        let grammar = expand_tokens(&ExtractedLexicalGrammar {
+            variables: vec![
+                Variable::anonymous("if", Rule::String("if".to_string())),
+                Variable::auxiliary("identifier", Rule::Pattern("[a-z]+".to_string())),
+                Variable {
+                    name: "number".to_string(),
+                    kind: VariableType::Named,
+                    rule: Rule::immediate_token(Rule::prec(
+                        Precedence::Integer(3),
+                        Rule::Pattern("\\d+".to_string()),
+                    )),
+                },
+            ],
+            separators: vec![Rule::Pattern("\\s".to_string())],
+        })
+        .unwrap();
+
+        assert_eq!(
+            grammar
+                .variables
+                .iter()
+                .map(|variable| variable.implicit_precedence)
+                .collect::<Vec<_>>(),
+            vec![2, 0, 1]
+        );
+        assert!(grammar
+            .variables
+            .windows(2)
+            .all(|pair| pair[0].start_state < pair[1].start_state));
+        assert_eq!(
+            grammar.variables.last().unwrap().start_state,
+            grammar.nfa.last_state_id()
+        );
+
+        assert_eq!(simulate_lexer(&grammar, "  iff"), Some((1, "iff")));
+        assert_eq!(simulate_lexer(&grammar, "12 "), Some((2, "12")));
+        // Immediate tokens can't be preceded by separators.
+        assert_eq!(simulate_lexer(&grammar, " 12"), None);
+    }
+
+    #[test]
+    fn test_expand_pattern() {
+        let table = [
+            // literals, classes and repetitions
+            (
+                r"a[bc]+d?",
+                vec!["ab", "acbc", "abd"],
+                vec!["a", "ad", "abdd"],
+            ),
+            // alternation and groups
+            (
+                r"(foo|ba(r|z))\d{2}",
+                vec!["foo12", "baz00"],
+                vec!["ba12", "foo1"],
+            ),
+            // bounded repetition
+            (r"x{2,3}", vec!["xx", "xxx"], vec!["x", "xxxx"]),
+            // negated classes and perl classes
+            (r"[^\s\d]\w*", vec!["a1", "_"], vec!["1a", " "]),
+            // unicode classes
+            (r"\p{Zs}[\p{L}]", vec!["\u{3000}é", " a"], vec!["a ", "é"]),
+            // escapes that javascript allows but rust does not require
+            (r#"\/\"'"#, vec![r#"/"'"#], vec![r#"\/"'"#]),
+            // braces that javascript treats as literals
+            (r"u{[0-9]+}", vec!["u{12}"], vec!["u12"]),
+        ];
+
+        for (pattern, examples, counter_examples) in table {
+            let (nfa, start_state) = expand_pattern(pattern).unwrap();
+            for example in examples {
+                assert!(
+                    NfaCursor::new(&nfa, vec![start_state]).matches(example),
+                    "/{}/ should match {:?}",
+                    pattern,
+                    example
+                );
+            }
+            for counter_example in counter_examples {
+                assert!(
+                    !NfaCursor::new(&nfa, vec![start_state]).matches(counter_example),
+                    "/{}/ should not match {:?}",
+                    pattern,
+                    counter_example
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_expand_pattern_errors() {
+        assert!(expand_pattern(r"a(?=b)").is_err());
+        assert!(expand_pattern(r"^a").is_err());
+    }
+}
@@ -0,0 +1,294 @@
+use super::InternedGrammar;
+use crate::tree_sitter_cli::grammars::{InputGrammar, Variable, VariableType};
+use crate::tree_sitter_cli::rules::{Rule, Symbol};
+use anyhow::{anyhow, Result};
+
+pub(crate) fn intern_symbols(grammar: &InputGrammar) -> Result<InternedGrammar> {
+    let interner = Interner { grammar };
+
+    if grammar
+        .variables
+        .first()
+        .is_some_and(|variable| variable_type_for_name(&variable.name) == VariableType::Hidden)
+    {
+        return Err(anyhow!("A grammar's start rule must be visible."));
+    }
+
+    let mut variables = Vec::with_capacity(grammar.variables.len());
+    for variable in grammar.variables.iter() {
+        variables.push(Variable {
+            name: variable.name.clone(),
+            kind: variable_type_for_name(&variable.name),
+            rule: interner.intern_rule(&variable.rule, &variable.name)?,
+        });
+    }
+
+    let mut external_tokens = Vec::with_capacity(grammar.external_tokens.len());
+    for external_token in grammar.external_tokens.iter() {
+        let rule = interner.intern_rule(&external_token, "externals")?;
+        let (name, kind) = if let Rule::NamedSymbol(name) = external_token {
+            (name.clone(), variable_type_for_name(&name))
+        } else {
+            (String::new(), VariableType::Anonymous)
+        };
+        external_tokens.push(Variable { name, kind, rule });
+    }
+
+    let mut extra_symbols = Vec::with_capacity(grammar.extra_symbols.len());
+    for extra_token in grammar.extra_symbols.iter() {
+        extra_symbols.push(interner.intern_rule(extra_token, "extras")?);
+    }
+
+    let mut supertype_symbols = Vec::with_capacity(grammar.supertype_symbols.len());
+    for supertype_symbol_name in grammar.supertype_symbols.iter() {
+        supertype_symbols.push(
+            interner
+                .intern_name(supertype_symbol_name)
+                .ok_or_else(|| undefined_symbol(supertype_symbol_name, "supertypes"))?,
+        );
+    }
+
+    let mut expected_conflicts = Vec::new();
+    for conflict in grammar.expected_conflicts.iter() {
+        let mut interned_conflict = Vec::with_capacity(conflict.len());
+        for name in conflict {
+            interned_conflict.push(
+                interner
+                    .intern_name(&name)
+                    .ok_or_else(|| undefined_symbol(name, "conflicts"))?,
+            );
+        }
+        expected_conflicts.push(interned_conflict);
+    }
+
+    let mut variables_to_inline = Vec::new();
+    for name in grammar.variables_to_inline.iter() {
+        if let Some(symbol) = interner.intern_name(&name) {
+            variables_to_inline.push(symbol);
+        }
+    }
+
+    let mut word_token = None;
+    if let Some(name) = grammar.word_token.as_ref() {
+        word_token = Some(
+            interner
+                .intern_name(&name)
+                .ok_or_else(|| undefined_symbol(name, "word"))?,
+        );
+    }
+
+    for (i, variable) in variables.iter_mut().enumerate() {
+        if supertype_symbols.contains(&Symbol::non_terminal(i)) {
+            variable.kind = VariableType::Hidden;
+        }
+    }
+
+    Ok(InternedGrammar {
+        variables,
+        external_tokens,
+        extra_symbols,
+        expected_conflicts,
+        variables_to_inline,
+        supertype_symbols,
+        word_token,
+        precedence_orderings: grammar.precedence_orderings.clone(),
+    })
+}
+
+struct Interner<'a> {
+    grammar: &'a InputGrammar,
+}
+
+impl<'a> Interner<'a> {
+    /// Replaces the names in `rule` with symbols, `referenced_from` naming where the rule is
+    /// defined for errors about undefined symbols.
+    fn intern_rule(&self, rule: &Rule, referenced_from: &str) -> Result<Rule> {
+        match rule {
+            Rule::Choice(elements) => {
+                let mut result = Vec::with_capacity(elements.len());
+                for element in elements {
+                    result.push(self.intern_rule(element, referenced_from)?);
+                }
+                Ok(Rule::Choice(result))
+            }
+            Rule::Seq(elements) => {
+                let mut result = Vec::with_capacity(elements.len());
+                for element in elements {
+                    result.push(self.intern_rule(element, referenced_from)?);
+                }
+                Ok(Rule::Seq(result))
+            }
+            Rule::Repeat(content) => Ok(Rule::Repeat(Box::new(
+                self.intern_rule(content, referenced_from)?,
+            ))),
+            Rule::Metadata { rule, params } => Ok(Rule::Metadata {
+                rule: Box::new(self.intern_rule(rule, referenced_from)?),
+                params: params.clone(),
+            }),
+
+            Rule::NamedSymbol(name) => {
+                if let Some(symbol) = self.intern_name(&name) {
+                    Ok(Rule::Symbol(symbol))
+                } else {
+                    Err(undefined_symbol(name, referenced_from))
+                }
+            }
+
+            _ => Ok(rule.clone()),
+        }
+    }
+
+    fn intern_name(&self, symbol: &str) -> Option<Symbol> {
+        for (i, variable) in self.grammar.variables.iter().enumerate() {
+            if variable.name == symbol {
+                return Some(Symbol::non_terminal(i));
+            }
+        }
+
+        for (i, external_token) in self.grammar.external_tokens.iter().enumerate() {
+            if let Rule::NamedSymbol(name) = external_token {
+                if name == symbol {
+                    return Some(Symbol::external(i));
+                }
+            }
+        }
+
+        return None;
+    }
+}
+
+fn undefined_symbol(name: &str, referenced_from: &str) -> anyhow::Error {
+    anyhow!("Undefined symbol `{}` in `{}`", name, referenced_from)
+}
+
+fn variable_type_for_name(name: &str) -> VariableType {
+    if name.starts_with("_") {
+        VariableType::Hidden
+    } else {
+        VariableType::Named
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basic_repeat_expansion() {
+        let grammar = intern_symbols(&build_grammar(vec![
+            Variable::named("x", Rule::choice(vec![Rule::named("y"), Rule::named("_z")])),
+            Variable::named("y", Rule::named("_z")),
+            Variable::named("_z", Rule::string("a")),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            grammar.variables,
+            vec![
+                Variable::named(
+                    "x",
+                    Rule::choice(vec![Rule::non_terminal(1), Rule::non_terminal(2),])
+                ),
+                Variable::named("y", Rule::non_terminal(2)),
+                Variable::hidden("_z", Rule::string("a")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interning_external_token_names() {
+        // Variable `y` is both an internal and an external token.
+        // Variable `z` is just an external token.
+        let mut input_grammar = build_grammar(vec![
+            Variable::named(
+                "w",
+                Rule::choice(vec![Rule::named("x"), Rule::named("y"), Rule::named("z")]),
+            ),
+            Variable::named("x", Rule::string("a")),
+            Variable::named("y", Rule::string("b")),
+        ]);
+        input_grammar
+            .external_tokens
+            .extend(vec![Rule::named("y"), Rule::named("z")]);
+
+        let grammar = intern_symbols(&input_grammar).unwrap();
+
+        // Variable `y` is referred to by its internal index.
+        // Variable `z` is referred to by its external index.
+        assert_eq!(
+            grammar.variables,
+            vec![
+                Variable::named(
+                    "w",
+                    Rule::choice(vec![
+                        Rule::non_terminal(1),
+                        Rule::non_terminal(2),
+                        Rule::external(1),
+                    ])
+                ),
+                Variable::named("x", Rule::string("a")),
+                Variable::named("y", Rule::string("b")),
+            ]
+        );
+
+        // The external token for `y` refers back to its internal index.
+        assert_eq!(
+            grammar.external_tokens,
+            vec![
+                Variable::named("y", Rule::non_terminal(2)),
+                Variable::named("z", Rule::external(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interning_extras_supertypes_inline_and_word() {
+        let mut input_grammar = build_grammar(vec![
+            Variable::named("program", Rule::named("expression")),
+            Variable::named("expression", Rule::named("identifier")),
+            Variable::named("identifier", Rule::pattern("[a-z]+")),
+            Variable::named("comment", Rule::string("#")),
+        ]);
+        input_grammar.extra_symbols = vec![Rule::named("comment"), Rule::pattern("\\s")];
+        input_grammar.supertype_symbols = vec!["expression".to_string()];
+        input_grammar.variables_to_inline = vec!["expression".to_string(), "gone".to_string()];
+        input_grammar.word_token = Some("identifier".to_string());
+
+        let grammar = intern_symbols(&input_grammar).unwrap();
+
+        assert_eq!(
+            grammar.extra_symbols,
+            vec![Rule::non_terminal(3), Rule::pattern("\\s")]
+        );
+        assert_eq!(grammar.supertype_symbols, vec![Symbol::non_terminal(1)]);
+        assert_eq!(grammar.variables_to_inline, vec![Symbol::non_terminal(1)]);
+        assert_eq!(grammar.word_token, Some(Symbol::non_terminal(2)));
+        // Supertypes don't show up in the syntax tree.
+        assert_eq!(grammar.variables[1].kind, VariableType::Hidden);
+    }
+
+    #[test]
+    fn test_grammar_with_undefined_symbols() {
+        let result = intern_symbols(&build_grammar(vec![Variable::named("x", Rule::named("y"))]));
+
+        match result {
+            Err(e) => assert_eq!(e.to_string(), "Undefined symbol `y` in `x`"),
+            _ => panic!("Expected an error but got none"),
+        }
+
+        let mut input_grammar = build_grammar(vec![Variable::named("x", Rule::string("a"))]);
+        input_grammar.word_token = Some("identifier".to_string());
+        match intern_symbols(&input_grammar) {
+            Err(e) => assert_eq!(e.to_string(), "Undefined symbol `identifier` in `word`"),
+            _ => panic!("Expected an error but got none"),
+        }
+    }
+
+    fn build_grammar(variables: Vec<Variable>) -> InputGrammar {
+        InputGrammar {
+            variables,
+            name: "the_language".to_string(),
+            ..Default::default()
+        }
+    }
+}
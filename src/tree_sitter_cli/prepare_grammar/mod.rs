@@ -0,0 +1,54 @@
+mod expand_repeats;
+mod expand_tokens;
+mod extract_tokens;
+mod flatten_grammar;
+mod intern_symbols;
+mod process_inlines;
+
+pub(crate) use self::expand_repeats::expand_repeats;
+pub(crate) use self::expand_tokens::{expand_pattern, expand_tokens};
+pub(crate) use self::extract_tokens::extract_tokens;
+pub(crate) use self::flatten_grammar::flatten_grammar;
+pub(crate) use self::intern_symbols::intern_symbols;
+pub(crate) use self::process_inlines::process_inlines;
+
+use super::grammars::{ExternalToken, PrecedenceEntry, Variable, VariableType};
+use super::rules::{Rule, Symbol};
+
+#[derive(Clone, Debug)]
+pub(crate) struct IntermediateGrammar<T, U> {
+    pub variables: Vec<Variable>,
+    pub extra_symbols: Vec<T>,
+    pub expected_conflicts: Vec<Vec<Symbol>>,
+    pub precedence_orderings: Vec<Vec<PrecedenceEntry>>,
+    pub external_tokens: Vec<U>,
+    pub variables_to_inline: Vec<Symbol>,
+    pub supertype_symbols: Vec<Symbol>,
+    pub word_token: Option<Symbol>,
+}
+
+pub(crate) type InternedGrammar = IntermediateGrammar<Rule, Variable>;
+pub(crate) type ExtractedSyntaxGrammar = IntermediateGrammar<Symbol, ExternalToken>;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ExtractedLexicalGrammar {
+    pub variables: Vec<Variable>,
+    pub separators: Vec<Rule>,
+}
+
+impl<T, U> IntermediateGrammar<T, U> {
+    /// Inlined variables are spliced into the productions that use them, so just like hidden
+    /// variables they don't show up as nodes in the syntax tree.
+    pub(crate) fn hide_inlined_variables(&mut self) {
+        for symbol in &self.variables_to_inline {
+            if !symbol.is_non_terminal() {
+                continue;
+            }
+            if let Some(variable) = self.variables.get_mut(symbol.index) {
+                if variable.kind == VariableType::Named {
+                    variable.kind = VariableType::Hidden;
+                }
+            }
+        }
+    }
+}